@@ -0,0 +1,463 @@
+//! Client that sends documents directly to the X-Ray API.
+//!
+//! [`ApiClient`] is useful where the X-Ray daemon is not available; e.g.,
+//! batch environments that cannot run a daemon sidecar.
+//!
+//! ```no_run
+//! use aws_config::BehaviorVersion;
+//! use xray_lite::{Context as _, CustomNamespace, SubsegmentContext};
+//! use xray_lite_aws_sdk::api_client::ApiClient;
+//!
+//! async fn run_batch() {
+//!     let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+//!     let client = ApiClient::new(aws_sdk_xray::Client::new(&config)).unwrap();
+//!     let context = SubsegmentContext::from_lambda_env(client.clone()).unwrap();
+//!     {
+//!         let _session = context.enter_subsegment(CustomNamespace::new("batch"));
+//!         // do the job ...
+//!     }
+//!     // pending documents are also flushed when the last clone is dropped
+//!     client.flush().unwrap();
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use xray_lite::{report_error, Client, ClientStats, Error, Result};
+
+/// Maximum number of documents in a `PutTraceSegments` request.
+const MAX_BATCH_SIZE: usize = 50;
+
+/// Client that sends documents with the `PutTraceSegments` API.
+///
+/// Documents are queued and sent in batches of up to 50 documents by a
+/// background thread, which has its own single-threaded Tokio runtime. A batch
+/// is sent when it is full, when [`ApiClient::flush`] is called, or when the
+/// flush interval has elapsed since its first document was queued. Documents
+/// reported in `UnprocessedTraceSegments` are retried with exponential
+/// backoff.
+///
+/// Pending documents are flushed when the last clone of the client is
+/// dropped. Documents given up after the maximum number of attempts are
+/// counted as queue drops in [`Client::stats`] and reported to the
+/// [error hook][xray_lite::set_error_hook].
+#[derive(Clone, Debug)]
+pub struct ApiClient {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    // `None` only while dropped
+    sender: Option<Sender<Message>>,
+    worker: Option<JoinHandle<()>>,
+    stats: ClientStats,
+}
+
+#[derive(Debug)]
+enum Message {
+    Document(String),
+    Flush(Sender<()>),
+}
+
+impl ApiClient {
+    /// Default interval to send pending documents.
+    pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Default maximum number of attempts to send a document.
+    pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+    /// Creates a client with the default options.
+    pub fn new(client: aws_sdk_xray::Client) -> Result<Self> {
+        Self::builder(client).build()
+    }
+
+    /// Starts building a client.
+    pub fn builder(client: aws_sdk_xray::Client) -> ApiClientBuilder {
+        ApiClientBuilder {
+            client,
+            flush_interval: Self::DEFAULT_FLUSH_INTERVAL,
+            max_attempts: Self::DEFAULT_MAX_ATTEMPTS,
+            retry_backoff: Duration::from_millis(100),
+        }
+    }
+
+    /// Sends the pending documents and waits until they are sent or given up.
+    pub fn flush(&self) -> Result<()> {
+        let (done, wait) = mpsc::channel();
+        self.inner.send(Message::Flush(done))?;
+        wait.recv().map_err(|_| worker_stopped())
+    }
+}
+
+impl Client for ApiClient {
+    /// Queues a document.
+    ///
+    /// Fails with [`Error::TransportClosed`] if the background thread has
+    /// stopped.
+    fn send<S>(&self, data: &S) -> Result<()>
+    where
+        S: Serialize,
+    {
+        let document = serde_json::to_string(data).inspect_err(|_| {
+            self.inner.stats.record_serialization_failure();
+        })?;
+        self.inner
+            .send(Message::Document(document))
+            .inspect_err(|_| self.inner.stats.record_queue_drops(1))
+    }
+
+    fn stats(&self) -> Option<&ClientStats> {
+        Some(&self.inner.stats)
+    }
+}
+
+impl Inner {
+    fn send(&self, message: Message) -> Result<()> {
+        self.sender
+            .as_ref()
+            .ok_or_else(worker_stopped)?
+            .send(message)
+            .map_err(|_| worker_stopped())
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // disconnecting the channel stops the worker after a final flush
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Builder of an [`ApiClient`].
+#[derive(Debug)]
+pub struct ApiClientBuilder {
+    client: aws_sdk_xray::Client,
+    flush_interval: Duration,
+    max_attempts: u32,
+    retry_backoff: Duration,
+}
+
+impl ApiClientBuilder {
+    /// Updates the interval to send pending documents.
+    pub fn with_flush_interval(self, flush_interval: Duration) -> Self {
+        Self {
+            flush_interval,
+            ..self
+        }
+    }
+
+    /// Updates the maximum number of attempts to send a document.
+    pub fn with_max_attempts(self, max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..self
+        }
+    }
+
+    /// Updates the delay before the first retry, which doubles every retry.
+    pub fn with_retry_backoff(self, retry_backoff: Duration) -> Self {
+        Self {
+            retry_backoff,
+            ..self
+        }
+    }
+
+    /// Builds the client and starts its background thread.
+    pub fn build(self) -> Result<ApiClient> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let (sender, receiver) = mpsc::channel();
+        let stats = ClientStats::new();
+        let worker = Worker {
+            config: self,
+            stats: stats.clone(),
+        };
+        let worker = std::thread::Builder::new()
+            .name("xray-lite-api-client".into())
+            .spawn(move || worker.run(runtime, receiver))?;
+        Ok(ApiClient {
+            inner: Arc::new(Inner {
+                sender: Some(sender),
+                worker: Some(worker),
+                stats,
+            }),
+        })
+    }
+}
+
+struct Worker {
+    config: ApiClientBuilder,
+    stats: ClientStats,
+}
+
+impl Worker {
+    fn run(self, runtime: tokio::runtime::Runtime, receiver: Receiver<Message>) {
+        let mut batch = Vec::with_capacity(MAX_BATCH_SIZE);
+        // when the pending batch is due; `None` while the batch is empty
+        let mut deadline: Option<Instant> = None;
+        loop {
+            let message = match deadline {
+                Some(deadline) => {
+                    receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match message {
+                Ok(Message::Document(document)) => {
+                    if batch.is_empty() {
+                        deadline = Some(Instant::now() + self.config.flush_interval);
+                    }
+                    batch.push(document);
+                    if batch.len() >= MAX_BATCH_SIZE {
+                        runtime.block_on(self.put(std::mem::take(&mut batch)));
+                        deadline = None;
+                    }
+                }
+                Ok(Message::Flush(done)) => {
+                    runtime.block_on(self.put(std::mem::take(&mut batch)));
+                    deadline = None;
+                    let _ = done.send(());
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    runtime.block_on(self.put(std::mem::take(&mut batch)));
+                    deadline = None;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    runtime.block_on(self.put(std::mem::take(&mut batch)));
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn put(&self, mut documents: Vec<String>) {
+        let mut backoff = self.config.retry_backoff;
+        for attempt in 1..=self.config.max_attempts {
+            if documents.is_empty() {
+                return;
+            }
+            if attempt > 1 {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            let mut unprocessed = Vec::new();
+            for chunk in documents.chunks(MAX_BATCH_SIZE) {
+                let result = self
+                    .config
+                    .client
+                    .put_trace_segments()
+                    .set_trace_segment_documents(Some(chunk.to_vec()))
+                    .send()
+                    .await;
+                match result {
+                    Ok(output) => {
+                        let ids: HashSet<&str> = output
+                            .unprocessed_trace_segments()
+                            .iter()
+                            .filter_map(|segment| segment.id())
+                            .collect();
+                        for document in chunk {
+                            if document_id(document).is_some_and(|id| ids.contains(&*id)) {
+                                unprocessed.push(document.clone());
+                            } else {
+                                self.stats.record_sent(document.len());
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        self.stats.record_socket_error();
+                        if attempt == self.config.max_attempts {
+                            report_error(
+                                "failed to put trace segments",
+                                &Error::IO(std::io::Error::other(e)),
+                            );
+                        }
+                        unprocessed.extend_from_slice(chunk);
+                    }
+                }
+            }
+            documents = unprocessed;
+        }
+        if !documents.is_empty() {
+            self.stats.record_queue_drops(documents.len());
+            report_error(
+                "gave up putting trace segments",
+                &Error::IO(std::io::Error::other(format!(
+                    "{} documents remained unprocessed",
+                    documents.len()
+                ))),
+            );
+        }
+    }
+}
+
+fn document_id(document: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(document)
+        .ok()?
+        .get("id")?
+        .as_str()
+        .map(str::to_string)
+}
+
+fn worker_stopped() -> Error {
+    Error::TransportClosed { source: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_xray::config::{BehaviorVersion, Credentials, Region};
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn xray_client(replay: &StaticReplayClient) -> aws_sdk_xray::Client {
+        aws_sdk_xray::Client::from_conf(
+            aws_sdk_xray::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(Credentials::new(
+                    "ATESTCLIENT",
+                    "astestsecretkey",
+                    None,
+                    None,
+                    "test-credentials",
+                ))
+                .region(Region::new("us-east-1"))
+                .http_client(replay.clone())
+                .build(),
+        )
+    }
+
+    fn event(unprocessed_ids: &[&str]) -> ReplayEvent {
+        let unprocessed: Vec<Value> = unprocessed_ids
+            .iter()
+            .map(|id| json!({"Id": id, "ErrorCode": "Throttled"}))
+            .collect();
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri("https://xray.us-east-1.amazonaws.com/TraceSegments")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(200)
+                .body(SdkBody::from(
+                    json!({"UnprocessedTraceSegments": unprocessed}).to_string(),
+                ))
+                .unwrap(),
+        )
+    }
+
+    fn document(i: usize) -> Value {
+        json!({"id": format!("{i:016x}"), "name": "test"})
+    }
+
+    // returns the IDs of the documents in each request.
+    fn requested_ids(replay: &StaticReplayClient) -> Vec<Vec<String>> {
+        replay
+            .actual_requests()
+            .map(|request| {
+                let body: Value = serde_json::from_slice(request.body().bytes().unwrap()).unwrap();
+                body["TraceSegmentDocuments"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|document| document_id(document.as_str().unwrap()).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn api_client_should_send_batches_of_50() {
+        let replay = StaticReplayClient::new(vec![event(&[]), event(&[]), event(&[])]);
+        let client = ApiClient::builder(xray_client(&replay))
+            .with_flush_interval(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        for i in 0..120 {
+            client.send(&document(i)).unwrap();
+        }
+        client.flush().unwrap();
+        let sizes: Vec<usize> = requested_ids(&replay).iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![50, 50, 20]);
+    }
+
+    #[test]
+    fn api_client_should_retry_unprocessed_documents() {
+        let replay = StaticReplayClient::new(vec![event(&["0000000000000001"]), event(&[])]);
+        let client = ApiClient::builder(xray_client(&replay))
+            .with_flush_interval(Duration::from_secs(60))
+            .with_retry_backoff(Duration::from_millis(1))
+            .build()
+            .unwrap();
+        for i in 0..3 {
+            client.send(&document(i)).unwrap();
+        }
+        client.flush().unwrap();
+        let requests = requested_ids(&replay);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1], vec!["0000000000000001"]);
+        assert_eq!(client.stats().unwrap().snapshot().documents_sent, 3);
+    }
+
+    #[test]
+    fn api_client_should_count_given_up_documents_as_queue_drops() {
+        let replay = StaticReplayClient::new(vec![event(&["0000000000000001"])]);
+        let client = ApiClient::builder(xray_client(&replay))
+            .with_flush_interval(Duration::from_secs(60))
+            .with_max_attempts(1)
+            .build()
+            .unwrap();
+        for i in 0..2 {
+            client.send(&document(i)).unwrap();
+        }
+        client.flush().unwrap();
+        let stats = client.stats().unwrap().snapshot();
+        assert_eq!(stats.documents_sent, 1);
+        assert_eq!(stats.queue_drops, 1);
+    }
+
+    #[test]
+    fn api_client_should_send_partial_batch_after_flush_interval() {
+        let replay = StaticReplayClient::new((0..4).map(|_| event(&[])).collect());
+        let interval = Duration::from_millis(400);
+        let client = ApiClient::builder(xray_client(&replay))
+            .with_flush_interval(interval)
+            .build()
+            .unwrap();
+        // a document every half interval must not postpone the batch
+        for i in 0..4 {
+            client.send(&document(i)).unwrap();
+            std::thread::sleep(interval / 2);
+        }
+        let requests = requested_ids(&replay);
+        assert!(!requests.is_empty());
+        assert_eq!(requests[0][0], "0000000000000000");
+    }
+
+    #[test]
+    fn api_client_should_flush_on_drop() {
+        let replay = StaticReplayClient::new(vec![event(&[])]);
+        let client = ApiClient::builder(xray_client(&replay))
+            .with_flush_interval(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        let clone = client.clone();
+        client.send(&document(1)).unwrap();
+        drop(client);
+        assert!(requested_ids(&replay).is_empty());
+        drop(clone);
+        assert_eq!(requested_ids(&replay), vec![vec!["0000000000000001"]]);
+    }
+}
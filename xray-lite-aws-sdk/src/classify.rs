@@ -1,15 +1,15 @@
 //! Provides the [`ClassifyAwsIntercept`] SDK interceptor, which propagates trace context to downstream
 //! SDK calls and publishes SDK call segments to the lambda XRay daemon.
-//! 
+//!
 //! The interceptor uses two strategy traits:
 //! - [`RequestClassifier`] inspects the outbound Smithy Request to determine the target AWS service and operation.
 //! - [`ContextLookup`] provides the parent trace_id and segment_id for outbound AWS service calls.
-//! 
+//!
 //! Default implementations are provided for both traits.
-//! 
+//!
 //! XRay reporting from this interceptor is best-effort. Failure to collect or transmit XRay segments
 //! or trace data will not panic or disrupt request processing.
-//! 
+//!
 //! ## Example
 //! ```no_run
 //! use aws_config::BehaviorVersion;
@@ -21,7 +21,7 @@
 //!     let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
 //!     let s3_config = aws_sdk_s3::Config::new(&sdk_config).to_builder().interceptor(interceptor).build();
 //!     let s3_client = aws_sdk_s3::Client::from_conf(s3_config);
-//! 
+//!
 //!     // The trace for this lambda invocation will now include an S3.GetObject segment.
 //!     s3_client
 //!         .get_object()
@@ -39,11 +39,8 @@ use std::sync::RwLock;
 use aws_smithy_runtime_api::box_error::BoxError;
 use aws_smithy_runtime_api::client::{
     interceptors::{
+        context::{BeforeTransmitInterceptorContextMut, FinalizerInterceptorContextRef},
         Intercept,
-        context::{
-            BeforeTransmitInterceptorContextMut, 
-            FinalizerInterceptorContextRef,
-        }
     },
     runtime_components::RuntimeComponents,
 };
@@ -51,62 +48,77 @@ use aws_smithy_runtime_api::http::Request;
 use aws_smithy_types::config_bag::{ConfigBag, Storable, StoreReplace};
 use aws_types::request_id::RequestId;
 use url::Url;
-use xray_lite::{AwsNamespace, Client as XRayClient, Context, DaemonClient, Header, SubsegmentContext, SubsegmentSession};
+use xray_lite::{
+    AwsNamespace, Client as XRayClient, Context, DaemonClient, Header, SubsegmentContext,
+    SubsegmentSession,
+};
 
 /// helper to extract the first value from a key-value pair iterator where the key
 /// matches the given name, case-insensitive. Works for both Url query parameters and
 /// HTTP headers.
 macro_rules! first_with_name {
     ($pairs:expr, $name:expr) => {
-        $pairs.filter_map(
-            |(name, value)| 
-            if name.to_lowercase() == $name {
-                Some(value)
-            } else {
-                None
-            }
-         )
-         .next()
+        $pairs
+            .filter_map(|(name, value)| {
+                if name.to_lowercase() == $name {
+                    Some(value)
+                } else {
+                    None
+                }
+            })
+            .next()
     };
 }
 
 /// A type alias for default interceptor configuration in a lambda execution environment.
-pub type StandardLambdaIntercept = ClassifyAwsIntercept<DaemonClient, KnownServices, LambdaContextLookup>;
+pub type StandardLambdaIntercept =
+    ClassifyAwsIntercept<DaemonClient, KnownServices, LambdaContextLookup>;
 
 /// A Smithy interceptor which publishes SDK trace segments to the lambda XRay daemon.
 #[derive(Clone, Debug)]
-pub struct ClassifyAwsIntercept<C, I, L> 
-    where C: XRayClient + 'static,
-          I: RequestClassifier,
-          L: ContextLookup,
+pub struct ClassifyAwsIntercept<C, I, L>
+where
+    C: XRayClient + 'static,
+    I: RequestClassifier,
+    L: ContextLookup,
 {
     client: C,
     classifier: I,
-    lookup: L
+    lookup: L,
 }
-impl<C, I, L> ClassifyAwsIntercept<C, I, L> 
-    where C: XRayClient,
-          I: RequestClassifier,
-          L: ContextLookup
+impl<C, I, L> ClassifyAwsIntercept<C, I, L>
+where
+    C: XRayClient,
+    I: RequestClassifier,
+    L: ContextLookup,
 {
     /// Create the interceptor using a [`DaemonClient`] and an instance of [`KnownServices`] to classify outbound AWS requests.
     /// ## Returns
     /// - Err if the client could not be initialized
     pub fn from_lambda_env() -> xray_lite::Result<StandardLambdaIntercept> {
         let client = DaemonClient::from_lambda_env()?;
-        Ok(ClassifyAwsIntercept::new(client, KnownServices, LambdaContextLookup))
+        Ok(ClassifyAwsIntercept::new(
+            client,
+            KnownServices,
+            LambdaContextLookup,
+        ))
     }
 
-    /// Create the interceptor using a provided [`XRayClient`], [`RequestClassifier`], and [`ContextLookup`]. 
+    /// Create the interceptor using a provided [`XRayClient`], [`RequestClassifier`], and [`ContextLookup`].
     pub fn new(client: C, classifier: I, lookup: L) -> Self {
-        Self { client, classifier, lookup }
+        Self {
+            client,
+            classifier,
+            lookup,
+        }
     }
 }
 
 impl<C, I, L> Intercept for ClassifyAwsIntercept<C, I, L>
-    where C: XRayClient,
-          I: RequestClassifier,
-          L: ContextLookup
+where
+    C: XRayClient,
+    I: RequestClassifier,
+    L: ContextLookup,
 {
     fn name(&self) -> &'static str {
         "XRayIntercept"
@@ -127,7 +139,8 @@ impl<C, I, L> Intercept for ClassifyAwsIntercept<C, I, L>
                         .headers_mut()
                         .insert(Header::NAME, trace_id);
                 }
-                cfg.interceptor_state().store_put(CurrentSubsegment(RwLock::new(session)));
+                cfg.interceptor_state()
+                    .store_put(CurrentSubsegment(RwLock::new(session)));
             }
         }
         Ok(())
@@ -139,7 +152,6 @@ impl<C, I, L> Intercept for ClassifyAwsIntercept<C, I, L>
         _runtime_components: &RuntimeComponents,
         cfg: &mut ConfigBag,
     ) -> Result<(), BoxError> {
-
         if let Some(session) = cfg.interceptor_state().load::<CurrentSubsegment<C>>() {
             session.finalize(context);
             // remove segment from the bag so that it can be dropped and transmitted to the daemon.
@@ -179,13 +191,13 @@ pub trait RequestClassifier: Clone + Debug + Send + Sync {
 }
 
 /// A [`RequestClassifier`] which works for a number of known AWS services.
-/// 
+///
 /// If the outbound request includes an "x-amz-target" header, that header value is used. This covers
 /// DynamoDB, Cognito, SQS, and some others. There is no official standard for this header, so the
 /// exact set of suppported services is difficult to determine.
-/// 
+///
 /// Otherwise if the outbound request targets an S3 endpoint, the x-id parameter is used (if present).
-/// 
+///
 /// Otherwise, None is returned.
 #[derive(Clone, Copy, Debug)]
 pub struct KnownServices;
@@ -198,7 +210,7 @@ impl RequestClassifier for KnownServices {
                 // [service].[operation]
                 2 => Some(AwsNamespace::new(parts[0], parts[1])),
                 // unknown usage.
-                _ => None
+                _ => None,
             };
         }
 
@@ -220,7 +232,7 @@ impl S3RequestClassifier {
     /// Otherwise, returns None.
     pub fn classify_url(url: &Url) -> Option<AwsNamespace> {
         // x-id is not part of documented S3 unfortunately. however it is a consistent behavior
-        // in the current rust SDK. we cover this with a unit test; it may be necessary to switch 
+        // in the current rust SDK. we cover this with a unit test; it may be necessary to switch
         // to a more robust strategy if a future SDK change breaks us.
         url.aws_service_code()
             .filter(|code| *code == "s3")
@@ -230,15 +242,15 @@ impl S3RequestClassifier {
 }
 impl RequestClassifier for S3RequestClassifier {
     fn classify_request(&self, request: &Request) -> Option<AwsNamespace> {
-        Url::try_parse_aws_url(request.uri())
-            .and_then(|u| Self::classify_url(&u))
+        Url::try_parse_aws_url(request.uri()).and_then(|u| Self::classify_url(&u))
     }
 }
 
 /// A helper trait for parsing AWS endpoint URLs.
 trait AwsServiceUrl {
     fn try_parse_aws_url(url: &str) -> Option<Self>
-        where Self: Sized;
+    where
+        Self: Sized;
 
     /// Extract the service-specific part of this Url, as documented in https://docs.aws.amazon.com/general/latest/gr/rande.html
     fn aws_service_code(&self) -> Option<&str>;
@@ -246,8 +258,14 @@ trait AwsServiceUrl {
 impl AwsServiceUrl for Url {
     fn try_parse_aws_url(url: &str) -> Option<Self> {
         match url.parse::<Url>() {
-            Ok(url) if url.domain().is_some_and(|endpoint| endpoint.ends_with(".amazonaws.com")) => Some(url),
-            _ => None
+            Ok(url)
+                if url
+                    .domain()
+                    .is_some_and(|endpoint| endpoint.ends_with(".amazonaws.com")) =>
+            {
+                Some(url)
+            }
+            _ => None,
         }
     }
 
@@ -256,7 +274,7 @@ impl AwsServiceUrl for Url {
         match components.len() {
             5 => Some(components[1]), // resource-specific endpoints, e.g. https://{bucket-name}.s3.{region}.amazonaws.com
             3 | 4 => Some(components[0]), // regional or global endpoints
-            _ => None
+            _ => None,
         }
     }
 }
@@ -265,19 +283,21 @@ impl AwsServiceUrl for Url {
 /// [`ConfigBag::interceptor_state`].
 #[derive(Debug)]
 struct CurrentSubsegment<C>(RwLock<SubsegmentSession<C, AwsNamespace>>)
-    where C: XRayClient + 'static;
+where
+    C: XRayClient + 'static;
 
-impl<C> Storable for CurrentSubsegment<C> 
-    where C: XRayClient + 'static
+impl<C> Storable for CurrentSubsegment<C>
+where
+    C: XRayClient + 'static,
 {
     type Storer = StoreReplace<Self>;
 }
 impl<C> CurrentSubsegment<C>
-    where C: XRayClient + 'static
+where
+    C: XRayClient + 'static,
 {
     /// Record the response status and request ID on the current SubsegmentSession.
-    fn finalize(&self, context: &FinalizerInterceptorContextRef<'_>) 
-    {
+    fn finalize(&self, context: &FinalizerInterceptorContextRef<'_>) {
         if let Ok(mut session) = self.0.write() {
             if let Some(namespace) = session.namespace_mut() {
                 if let Some(response) = context.response() {
@@ -294,7 +314,10 @@ impl<C> CurrentSubsegment<C>
 #[cfg(test)]
 mod tests {
 
-    use std::{env, sync::{Arc, Mutex}};
+    use std::{
+        env,
+        sync::{Arc, Mutex},
+    };
 
     use aws_config::BehaviorVersion;
     use aws_sdk_dynamodb::types::AttributeValue;
@@ -307,7 +330,10 @@ mod tests {
     use url::Url;
     use xray_lite::{AwsNamespace, Client};
 
-    use super::{AwsServiceUrl, ClassifyAwsIntercept, KnownServices, LambdaContextLookup, S3RequestClassifier};
+    use super::{
+        AwsServiceUrl, ClassifyAwsIntercept, KnownServices, LambdaContextLookup,
+        S3RequestClassifier,
+    };
 
     macro_rules! test_sdk_client {
         ($client_crate:ident, $replay_client:expr, $xray_client:expr) => {
@@ -323,7 +349,11 @@ mod tests {
                     ))
                     .region($client_crate::config::Region::new("us-east-1"))
                     .http_client($replay_client.clone())
-                    .interceptor(ClassifyAwsIntercept::new($xray_client.clone(), KnownServices, LambdaContextLookup))
+                    .interceptor(ClassifyAwsIntercept::new(
+                        $xray_client.clone(),
+                        KnownServices,
+                        LambdaContextLookup,
+                    ))
                     .build(),
             )
         };
@@ -332,11 +362,12 @@ mod tests {
     /// a mock XRay daemon client which accumulates messages in memory, for post-test verification.
     #[derive(Default, Debug, Clone)]
     struct TestXRayClient {
-        messages: Arc<Mutex<Vec<serde_json::Value>>>
+        messages: Arc<Mutex<Vec<serde_json::Value>>>,
     }
     impl Client for TestXRayClient {
         fn send<S>(&self, data: &S) -> xray_lite::Result<()>
-            where S: Serialize 
+        where
+            S: Serialize,
         {
             let json = serde_json::to_value(data)?;
             self.messages.lock().unwrap().push(json);
@@ -360,45 +391,60 @@ mod tests {
 
     #[test]
     fn parse_non_aws_url() {
-        assert_eq!(None, Url::try_parse_aws_url("https://s3.us-west-2.amazon.com"));
+        assert_eq!(
+            None,
+            Url::try_parse_aws_url("https://s3.us-west-2.amazon.com")
+        );
     }
 
     #[test]
     fn classify_s3_url() {
-        let url = Url::try_parse_aws_url("https://s3.us-west-2.amazonaws.com/test-bucket/test-key?x-id=GetObject").unwrap();
+        let url = Url::try_parse_aws_url(
+            "https://s3.us-west-2.amazonaws.com/test-bucket/test-key?x-id=GetObject",
+        )
+        .unwrap();
         assert_eq!(
-            format!("{:?}", AwsNamespace::new("S3", "GetObject")), 
-            format!("{:?}", S3RequestClassifier::classify_url(&url).unwrap()), 
+            format!("{:?}", AwsNamespace::new("S3", "GetObject")),
+            format!("{:?}", S3RequestClassifier::classify_url(&url).unwrap()),
         );
     }
 
     #[test]
     fn classify_s3_bucket_url() {
-        let url = Url::try_parse_aws_url("https://test-bucket.s3.us-west-2.amazonaws.com/test-key?x-id=GetObject").unwrap();
+        let url = Url::try_parse_aws_url(
+            "https://test-bucket.s3.us-west-2.amazonaws.com/test-key?x-id=GetObject",
+        )
+        .unwrap();
         assert_eq!(
-            format!("{:?}", AwsNamespace::new("S3", "GetObject")), 
-            format!("{:?}", S3RequestClassifier::classify_url(&url).unwrap()), 
+            format!("{:?}", AwsNamespace::new("S3", "GetObject")),
+            format!("{:?}", S3RequestClassifier::classify_url(&url).unwrap()),
         );
     }
 
     #[test]
     fn classify_unknown_s3_url() {
-        let url = Url::try_parse_aws_url("https://s3.us-west-2.amazonaws.com/test-bucket/test-key").unwrap();
+        let url = Url::try_parse_aws_url("https://s3.us-west-2.amazonaws.com/test-bucket/test-key")
+            .unwrap();
         assert!(S3RequestClassifier::classify_url(&url).is_none());
     }
 
     // tests dependent on static std::env setup must be run serially.
 
-    #[tokio::test] #[serial]
+    #[tokio::test]
+    #[serial]
     async fn no_trace_id() {
         let replay = StaticReplayClient::new(vec![s3_get_object("test-bucket", "some/key", None)]);
         let xray_client = TestXRayClient::default();
         let s3_client = test_sdk_client!(aws_sdk_s3, replay, xray_client);
 
         env::remove_var("_X_AMZN_TRACE_ID");
-        s3_client.get_object()
-            .bucket("test-bucket").key("some/key")
-            .send().await.unwrap();
+        s3_client
+            .get_object()
+            .bucket("test-bucket")
+            .key("some/key")
+            .send()
+            .await
+            .unwrap();
 
         // no trace data found in the environment.
         assert_eq!(0, xray_client.messages.lock().unwrap().len());
@@ -406,95 +452,123 @@ mod tests {
         replay.relaxed_requests_match();
     }
 
-    #[tokio::test] #[serial]
+    #[tokio::test]
+    #[serial]
     async fn classify_s3() {
-        let replay = StaticReplayClient::new(vec![
-            s3_get_object("test-bucket", "some/key", Some("Root=1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb"))
-        ]);
+        let replay = StaticReplayClient::new(vec![s3_get_object(
+            "test-bucket",
+            "some/key",
+            Some("Root=1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb"),
+        )]);
         let xray_client = TestXRayClient::default();
         let s3_client = test_sdk_client!(aws_sdk_s3, replay, xray_client);
 
-        env::set_var("_X_AMZN_TRACE_ID", "Root=1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb");
-        s3_client.get_object()
-            .bucket("test-bucket").key("some/key")
-            .send().await.unwrap();
+        env::set_var(
+            "_X_AMZN_TRACE_ID",
+            "Root=1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb",
+        );
+        s3_client
+            .get_object()
+            .bucket("test-bucket")
+            .key("some/key")
+            .send()
+            .await
+            .unwrap();
 
         let mut received_messages = xray_client.messages.lock().unwrap().clone();
-        let segment_id = received_messages[0].get("id").unwrap().as_str().unwrap().to_string();
+        let segment_id = received_messages[0]
+            .get("id")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string();
         // replace variable outputs with static values prior to assertions.
         normalize_messages(&mut received_messages);
 
         assert_eq!(
             vec![
                 json!({
-                    "name": "S3", "id": segment_id, 
-                    "start_time": 0.0, "trace_id": "1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb", 
+                    "name": "S3", "id": segment_id,
+                    "start_time": 0.0, "trace_id": "1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb",
                     "in_progress": true,
                     "namespace": "aws", "type": "subsegment", "aws": {"operation": "GetObject"},
                 }),
                 json!({
-                    "name": "S3", "id": segment_id, 
-                    "start_time": 0.0, "end_time": 1.0, "trace_id": "1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb", 
+                    "name": "S3", "id": segment_id,
+                    "start_time": 0.0, "end_time": 1.0, "trace_id": "1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb",
                     "namespace": "aws", "type": "subsegment", "http": {"response": {"status": 200}}, "aws": {"operation": "GetObject"}
-                })],
+                })
+            ],
             received_messages
         );
 
         let requests: Vec<&HttpRequest> = replay.actual_requests().collect();
         assert_eq!(1, requests.len());
         assert_eq!(
-            requests[0].headers().get("X-Amzn-Trace-Id").unwrap(), 
+            requests[0].headers().get("X-Amzn-Trace-Id").unwrap(),
             format!("Root=1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb;Parent={segment_id}")
         );
         replay.assert_requests_match(&["x-amz-user-agent", "authorization", "x-amzn-trace-id"]);
     }
 
-    #[tokio::test] #[serial]
+    #[tokio::test]
+    #[serial]
     async fn classify_ddb() {
-        let replay = StaticReplayClient::new(vec![
-            ReplayEvent::new(
-                http::Request::builder()
-                    .method("POST")
-                    .uri("https://dynamodb.us-east-1.amazonaws.com/")
-                    .body(SdkBody::empty())
-                    .unwrap(),
-                http::Response::builder()
-                    .status(404).body("Not Found".into())
-                    .unwrap(),
-            )
-        ]);
+        let replay = StaticReplayClient::new(vec![ReplayEvent::new(
+            http::Request::builder()
+                .method("POST")
+                .uri("https://dynamodb.us-east-1.amazonaws.com/")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(404)
+                .body("Not Found".into())
+                .unwrap(),
+        )]);
         let xray_client = TestXRayClient::default();
         let ddb_client = test_sdk_client!(aws_sdk_dynamodb, replay, xray_client);
 
-        env::set_var("_X_AMZN_TRACE_ID", "Root=1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb");
-        let _ = ddb_client.get_item()
-            .table_name("Foo").key("bar", AttributeValue::S("baz".into()))
-            .send().await;
+        env::set_var(
+            "_X_AMZN_TRACE_ID",
+            "Root=1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb",
+        );
+        let _ = ddb_client
+            .get_item()
+            .table_name("Foo")
+            .key("bar", AttributeValue::S("baz".into()))
+            .send()
+            .await;
 
         let mut received_messages = xray_client.messages.lock().unwrap().clone();
-        let segment_id = received_messages[0].get("id").unwrap().as_str().unwrap().to_string();
+        let segment_id = received_messages[0]
+            .get("id")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string();
         // replace variable outputs with static values prior to assertions.
         normalize_messages(&mut received_messages);
 
         assert_eq!(
             vec![
                 json!({
-                    "name": "DynamoDB_20120810", "id": segment_id, 
-                    "start_time": 0.0, "trace_id": "1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb", 
+                    "name": "DynamoDB_20120810", "id": segment_id,
+                    "start_time": 0.0, "trace_id": "1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb",
                     "in_progress": true,
                     "namespace": "aws", "type": "subsegment", "aws": {"operation": "GetItem"},
                 }),
                 json!({
-                    "name": "DynamoDB_20120810", "id": segment_id, 
-                    "start_time": 0.0, "end_time": 1.0, "trace_id": "1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb", 
+                    "name": "DynamoDB_20120810", "id": segment_id,
+                    "start_time": 0.0, "end_time": 1.0, "trace_id": "1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb",
                     "namespace": "aws", "type": "subsegment", "http": {"response": {"status": 404}}, "aws": {"operation": "GetItem"}
-                })],
+                })
+            ],
             received_messages
         );
         let requests: Vec<&HttpRequest> = replay.actual_requests().collect();
         assert_eq!(1, requests.len());
         assert_eq!(
-            requests[0].headers().get("X-Amzn-Trace-Id").unwrap(), 
+            requests[0].headers().get("X-Amzn-Trace-Id").unwrap(),
             format!("Root=1-aaaaaaaa-bbbbbbbbbbbbbbbbbbbbbbbb;Parent={segment_id}")
         );
     }
@@ -502,15 +576,21 @@ mod tests {
     fn s3_get_object(bucket: &str, key: &str, trace_id: Option<&str>) -> ReplayEvent {
         let mut request = http::Request::builder()
             .method("GET")
-            .uri(format!("https://{bucket}.s3.us-east-1.amazonaws.com/{key}?x-id=GetObject"))
+            .uri(format!(
+                "https://{bucket}.s3.us-east-1.amazonaws.com/{key}?x-id=GetObject"
+            ))
             .body(SdkBody::empty())
             .unwrap();
         if let Some(id) = trace_id {
-            request.headers_mut().insert("X-Amzn-Trace-Id", id.parse().unwrap());
+            request
+                .headers_mut()
+                .insert("X-Amzn-Trace-Id", id.parse().unwrap());
         }
-        ReplayEvent::new(request,
+        ReplayEvent::new(
+            request,
             http::Response::builder()
-                .status(200).body(SdkBody::from("hello, world"))
+                .status(200)
+                .body(SdkBody::from("hello, world"))
                 .unwrap(),
         )
     }
@@ -524,5 +604,4 @@ mod tests {
             }
         }
     }
-
-}
\ No newline at end of file
+}
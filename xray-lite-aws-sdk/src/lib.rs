@@ -38,19 +38,29 @@
 use std::sync::{Arc, Mutex};
 
 use aws_smithy_runtime_api::box_error::BoxError;
+#[cfg(any(feature = "sqs", feature = "sns"))]
+use aws_smithy_runtime_api::client::interceptors::context::BeforeSerializationInterceptorContextMut;
 use aws_smithy_runtime_api::client::interceptors::context::{
-    BeforeTransmitInterceptorContextMut, BeforeTransmitInterceptorContextRef,
-    FinalizerInterceptorContextRef,
+    BeforeSerializationInterceptorContextRef, BeforeTransmitInterceptorContextMut,
+    BeforeTransmitInterceptorContextRef, FinalizerInterceptorContextRef,
 };
 use aws_smithy_runtime_api::client::interceptors::Intercept;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::retries::RequestAttempts;
 use aws_smithy_runtime_api::client::runtime_components::RuntimeComponents;
 use aws_smithy_types::config_bag::ConfigBag;
 use aws_types::request_id::RequestId;
 
 use xray_lite::{AwsNamespace, Context, Header, SubsegmentSession};
 
+#[cfg(feature = "api-client")]
+pub mod api_client;
 #[cfg(feature = "classify")]
 pub mod classify;
+#[cfg(feature = "sns")]
+pub mod sns;
+#[cfg(feature = "sqs")]
+pub mod sqs;
 
 /// Extension of [`Context`].
 ///
@@ -61,13 +71,26 @@ pub trait ContextExt: Context + Clone + std::fmt::Debug + Send + Sync + 'static
     /// for the AWS service request.
     ///
     /// A returned `Intercept` implements the following hooks:
-    /// 1. [`read_before_attempt`](https://docs.rs/aws-smithy-runtime-api/1.1.7/aws_smithy_runtime_api/client/interceptors/trait.Intercept.html#method.read_before_attempt):
-    ///    Starts a subsegment of the AWS service request
-    /// 2. [`modify_before_transmit`](https://docs.rs/aws-smithy-runtime-api/1.1.7/aws_smithy_runtime_api/client/interceptors/trait.Intercept.html#method.modify_before_transmit):
+    /// 1. [`modify_before_serialization`](https://docs.rs/aws-smithy-runtime-api/1.1.7/aws_smithy_runtime_api/client/interceptors/trait.Intercept.html#method.modify_before_serialization):
+    ///    Starts the subsegment of the first attempt, and injects its trace
+    ///    header into the messages of SQS `SendMessage(Batch)` and SNS
+    ///    `Publish(Batch)` requests if the `sqs` and `sns` features are
+    ///    enabled respectively. As messages are serialized only once, those
+    ///    sent by a retry also refer to the first attempt.
+    /// 2. [`read_before_attempt`](https://docs.rs/aws-smithy-runtime-api/1.1.7/aws_smithy_runtime_api/client/interceptors/trait.Intercept.html#method.read_before_attempt):
+    ///    Starts a subsegment of the AWS service request for the attempt
+    ///    unless it has been started
+    /// 3. [`modify_before_transmit`](https://docs.rs/aws-smithy-runtime-api/1.1.7/aws_smithy_runtime_api/client/interceptors/trait.Intercept.html#method.modify_before_transmit):
     ///    Injects the `X-Amzn-Trace-Id` header into the request
-    /// 3. [`read_after_attempt`](https://docs.rs/aws-smithy-runtime-api/1.1.7/aws_smithy_runtime_api/client/interceptors/trait.Intercept.html#method.read_after_attempt):
-    ///    Updates the subsegment with the request ID and the response status,
-    ///    and reports the subsegment to the X-Ray daemon
+    /// 4. [`read_after_attempt`](https://docs.rs/aws-smithy-runtime-api/1.1.7/aws_smithy_runtime_api/client/interceptors/trait.Intercept.html#method.read_after_attempt):
+    ///    Updates the subsegment with the request ID, the response status,
+    ///    the number of preceding attempts as `aws.retries`, and the
+    ///    `throttle` flag if the request was throttled, and reports the
+    ///    subsegment to the X-Ray daemon
+    ///
+    /// A request is regarded as throttled if the response status is 429, or
+    /// the error code in the response is one of the throttling errors of the
+    /// AWS SDK; e.g., `ThrottlingException`.
     fn intercept_operation(
         &self,
         service: impl Into<String>,
@@ -75,6 +98,31 @@ pub trait ContextExt: Context + Clone + std::fmt::Debug + Send + Sync + 'static
     ) -> impl Intercept + 'static {
         XrayIntercept::new_with_operation(self.clone(), service, operation)
     }
+
+    /// Creates an [`Intercept`](https://docs.rs/aws-smithy-runtime-api/1.1.7/aws_smithy_runtime_api/client/interceptors/trait.Intercept.html)
+    /// that also wraps all the attempts of the AWS service request in a
+    /// subsegment of the operation.
+    ///
+    /// The subsegments of the attempts described in
+    /// [`intercept_operation`](Self::intercept_operation) become children of
+    /// the operation subsegment, which is entered in
+    /// [`read_before_execution`](https://docs.rs/aws-smithy-runtime-api/1.1.7/aws_smithy_runtime_api/client/interceptors/trait.Intercept.html#method.read_before_execution)
+    /// and reported in
+    /// [`read_after_execution`](https://docs.rs/aws-smithy-runtime-api/1.1.7/aws_smithy_runtime_api/client/interceptors/trait.Intercept.html#method.read_after_execution).
+    /// The operation subsegment has the request ID and the response status of
+    /// the last attempt, the total number of retries as `aws.retries`, and the
+    /// `throttle` flag if any attempt was throttled. Unlike the attempts, the
+    /// operation subsegment is not marked as an error if the last attempt
+    /// succeeded. Messages of SQS and SNS
+    /// requests refer to the operation subsegment instead of the first
+    /// attempt.
+    fn intercept_operation_with_parent(
+        &self,
+        service: impl Into<String>,
+        operation: impl Into<String>,
+    ) -> impl Intercept + 'static {
+        XrayIntercept::new_with_operation(self.clone(), service, operation).with_parent()
+    }
 }
 
 impl<T> ContextExt for T where T: Context + Clone + std::fmt::Debug + Send + Sync + 'static {}
@@ -91,6 +139,10 @@ where
     // immutable during its method calls.
     #[allow(clippy::type_complexity)]
     session: Arc<Mutex<Option<SubsegmentSession<T::Client, AwsNamespace>>>>,
+    // whether all the attempts are wrapped in an operation subsegment
+    with_parent: bool,
+    #[allow(clippy::type_complexity)]
+    operation_session: Arc<Mutex<Option<SubsegmentSession<T::Client, AwsNamespace>>>>,
 }
 
 impl<T> XrayIntercept<T>
@@ -107,8 +159,39 @@ where
             service: service.into(),
             operation: operation.into(),
             session: Arc::new(Mutex::new(None)),
+            with_parent: false,
+            operation_session: Arc::new(Mutex::new(None)),
         }
     }
+
+    fn with_parent(self) -> Self {
+        Self {
+            with_parent: true,
+            ..self
+        }
+    }
+
+    fn namespace(&self) -> AwsNamespace {
+        AwsNamespace::new(self.service.clone(), self.operation.clone())
+    }
+
+    // enters a subsegment of an attempt unless the current one is pending.
+    fn enter_attempt(&self) {
+        let mut session = self.session.lock().unwrap();
+        if session.is_some() {
+            return;
+        }
+        let parent_context = self
+            .operation_session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|s| s.child_context());
+        *session = Some(match parent_context {
+            Some(context) => context.enter_subsegment(self.namespace()),
+            None => self.context.enter_subsegment(self.namespace()),
+        });
+    }
 }
 
 impl<T> Intercept for XrayIntercept<T>
@@ -119,17 +202,57 @@ where
         "XrayIntercept"
     }
 
+    #[cfg(any(feature = "sqs", feature = "sns"))]
+    fn modify_before_serialization(
+        &self,
+        context: &mut BeforeSerializationInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        // messages refer to the subsegment that covers the attempts sending
+        // them, which has to exist before they are serialized
+        let trace_id = if self.with_parent {
+            self.operation_session
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(|s| s.x_amzn_trace_id())
+        } else {
+            self.enter_attempt();
+            self.session
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(|s| s.x_amzn_trace_id())
+        };
+        if let Some(Ok(header)) = trace_id.map(|trace_id| trace_id.parse::<Header>()) {
+            #[cfg(feature = "sqs")]
+            sqs::inject_input(context.input_mut(), &header);
+            #[cfg(feature = "sns")]
+            sns::inject_input(context.input_mut(), &header);
+        }
+        Ok(())
+    }
+
+    fn read_before_execution(
+        &self,
+        _context: &BeforeSerializationInterceptorContextRef<'_>,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        if self.with_parent {
+            let session = self.context.enter_subsegment(self.namespace());
+            *self.operation_session.lock().unwrap() = Some(session);
+        }
+        Ok(())
+    }
+
     fn read_before_attempt(
         &self,
         _context: &BeforeTransmitInterceptorContextRef<'_>,
         _runtime_components: &RuntimeComponents,
         _cfg: &mut ConfigBag,
     ) -> Result<(), BoxError> {
-        let session = self.context.enter_subsegment(AwsNamespace::new(
-            self.service.clone(),
-            self.operation.clone(),
-        ));
-        *self.session.lock().unwrap() = Some(session);
+        self.enter_attempt();
         Ok(())
     }
 
@@ -158,19 +281,354 @@ where
         &self,
         context: &FinalizerInterceptorContextRef<'_>,
         _runtime_components: &RuntimeComponents,
-        _cfg: &mut ConfigBag,
+        cfg: &mut ConfigBag,
     ) -> Result<(), BoxError> {
+        let retries = cfg
+            .load::<RequestAttempts>()
+            .map(|attempts| attempts.attempts().saturating_sub(1));
+        let throttled = context.response().is_some_and(is_throttled);
         let mut session = self.session.lock().unwrap();
         if let Some(mut session) = session.take() {
             if let Some(namespace) = session.namespace_mut() {
-                if let Some(response) = context.response() {
-                    namespace.response_status(response.status().as_u16());
-                    if let Some(request_id) = response.request_id() {
-                        namespace.request_id(request_id);
-                    }
-                }
+                update_namespace(namespace, context.response(), retries, throttled);
+            }
+        }
+        let mut operation_session = self.operation_session.lock().unwrap();
+        if let Some(namespace) = operation_session.as_mut().and_then(|s| s.namespace_mut()) {
+            if let Some(retries) = retries {
+                namespace.retries(retries);
+            }
+            if throttled {
+                namespace.throttled();
+            }
+        }
+        Ok(())
+    }
+
+    fn read_after_execution(
+        &self,
+        context: &FinalizerInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        // the first attempt has been entered but never made; e.g., if the
+        // request failed to be serialized
+        drop(self.session.lock().unwrap().take());
+        let mut operation_session = self.operation_session.lock().unwrap();
+        if let Some(mut session) = operation_session.take() {
+            if let Some(namespace) = session.namespace_mut() {
+                update_namespace(namespace, context.response(), None, false);
             }
         }
         Ok(())
     }
 }
+
+// error codes of throttled requests; the same as `THROTTLING_ERRORS` of
+// `aws-runtime`.
+const THROTTLING_ERRORS: &[&str] = &[
+    "Throttling",
+    "ThrottlingException",
+    "ThrottledException",
+    "RequestThrottledException",
+    "TooManyRequestsException",
+    "ProvisionedThroughputExceededException",
+    "TransactionInProgressException",
+    "RequestLimitExceeded",
+    "BandwidthLimitExceeded",
+    "LimitExceededException",
+    "RequestThrottled",
+    "SlowDown",
+    "PriorRequestNotComplete",
+    "EC2ThrottledException",
+];
+
+fn update_namespace(
+    namespace: &mut AwsNamespace,
+    response: Option<&HttpResponse>,
+    retries: Option<u32>,
+    throttled: bool,
+) {
+    if let Some(response) = response {
+        namespace.response_status(response.status().as_u16());
+        if let Some(request_id) = response.request_id() {
+            namespace.request_id(request_id);
+        }
+    }
+    if let Some(retries) = retries {
+        namespace.retries(retries);
+    }
+    if throttled {
+        namespace.throttled();
+    }
+}
+
+// whether the response indicates that the request was throttled.
+//
+// the error code is looked up in the `x-amzn-ErrorType` header of the JSON
+// protocols, or in the body if it has been loaded; i.e., `__type` of the JSON
+// protocols or `<Code>` of the XML protocols.
+fn is_throttled(response: &HttpResponse) -> bool {
+    if response.status().as_u16() == 429 {
+        return true;
+    }
+    if response.status().is_success() {
+        return false;
+    }
+    if let Some(error_type) = response.headers().get("x-amzn-ErrorType") {
+        // e.g., "ThrottlingException:http://internal.amazon.com/coral/..."
+        let code = error_type.split(':').next().unwrap_or_default();
+        let code = code.rsplit('#').next().unwrap_or_default();
+        if THROTTLING_ERRORS.contains(&code) {
+            return true;
+        }
+    }
+    let Some(body) = response.body().bytes() else {
+        return false;
+    };
+    let body = String::from_utf8_lossy(body);
+    THROTTLING_ERRORS.iter().any(|code| {
+        body.contains(&format!("<Code>{code}</Code>"))
+            || body.contains(&format!("#{code}\""))
+            || body.contains(&format!("\"__type\":\"{code}\""))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use aws_sdk_dynamodb::config::retry::RetryConfig;
+    use aws_sdk_dynamodb::config::{BehaviorVersion, Credentials, Region};
+    use aws_smithy_runtime::client::http::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_types::body::SdkBody;
+    use xray_lite::testing::{self, RecordingClient};
+    use xray_lite::{InProgressPolicy, SubsegmentContext};
+
+    use super::*;
+
+    fn context(client: &RecordingClient) -> SubsegmentContext<RecordingClient> {
+        testing::context(client).with_in_progress_policy(InProgressPolicy::Never)
+    }
+
+    fn dynamodb_client(replay: &StaticReplayClient) -> aws_sdk_dynamodb::Client {
+        aws_sdk_dynamodb::Client::from_conf(
+            aws_sdk_dynamodb::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(Credentials::new(
+                    "ATESTCLIENT",
+                    "astestsecretkey",
+                    None,
+                    None,
+                    "test-credentials",
+                ))
+                .region(Region::new("us-east-1"))
+                .retry_config(
+                    RetryConfig::standard()
+                        .with_max_attempts(3)
+                        .with_initial_backoff(Duration::from_millis(1)),
+                )
+                .http_client(replay.clone())
+                .build(),
+        )
+    }
+
+    fn event(status: u16, body: &str) -> ReplayEvent {
+        ReplayEvent::new(
+            http::Request::builder()
+                .uri("https://dynamodb.us-east-1.amazonaws.com/")
+                .body(SdkBody::empty())
+                .unwrap(),
+            http::Response::builder()
+                .status(status)
+                .header("x-amzn-RequestId", format!("request-{status}"))
+                .body(SdkBody::from(body))
+                .unwrap(),
+        )
+    }
+
+    fn throttled_event() -> ReplayEvent {
+        event(
+            400,
+            r#"{"__type":"com.amazonaws.dynamodb.v20120810#ProvisionedThroughputExceededException","message":"slow down"}"#,
+        )
+    }
+
+    async fn get_item(client: &aws_sdk_dynamodb::Client, intercept: impl Intercept + 'static) {
+        client
+            .get_item()
+            .table_name("the-table")
+            .customize()
+            .interceptor(intercept)
+            .send()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn intercept_operation_should_record_retries_of_attempts() {
+        let recorder = RecordingClient::default();
+        let context = context(&recorder);
+        let replay = StaticReplayClient::new(vec![throttled_event(), event(200, "{}")]);
+        get_item(
+            &dynamodb_client(&replay),
+            context.intercept_operation("DynamoDB", "GetItem"),
+        )
+        .await;
+        let documents = recorder.documents();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0]["name"], "DynamoDB");
+        assert_eq!(documents[0]["aws"]["retries"], 0);
+        assert_eq!(documents[0]["throttle"], true);
+        assert_eq!(documents[0]["error"], true);
+        assert_eq!(documents[0]["http"]["response"]["status"], 400);
+        assert_eq!(documents[1]["aws"]["retries"], 1);
+        assert!(documents[1].get("throttle").is_none());
+        assert_eq!(documents[1]["aws"]["request_id"], "request-200");
+        assert_eq!(documents[0]["parent_id"], "0123456789abcdef");
+        assert_eq!(documents[1]["parent_id"], "0123456789abcdef");
+    }
+
+    #[tokio::test]
+    async fn intercept_operation_with_parent_should_nest_attempts() {
+        let recorder = RecordingClient::default();
+        let context = context(&recorder);
+        let replay =
+            StaticReplayClient::new(vec![throttled_event(), throttled_event(), event(200, "{}")]);
+        get_item(
+            &dynamodb_client(&replay),
+            context.intercept_operation_with_parent("DynamoDB", "GetItem"),
+        )
+        .await;
+        let documents = recorder.documents();
+        assert_eq!(documents.len(), 4);
+        let operation = &documents[3];
+        assert_eq!(operation["name"], "DynamoDB");
+        assert_eq!(operation["parent_id"], "0123456789abcdef");
+        assert_eq!(operation["aws"]["operation"], "GetItem");
+        assert_eq!(operation["aws"]["retries"], 2);
+        assert_eq!(operation["aws"]["request_id"], "request-200");
+        assert_eq!(operation["http"]["response"]["status"], 200);
+        assert_eq!(operation["throttle"], true);
+        assert!(operation.get("error").is_none());
+        for (i, attempt) in documents[..3].iter().enumerate() {
+            assert_eq!(attempt["parent_id"], operation["id"]);
+            assert_eq!(attempt["aws"]["retries"], i);
+        }
+    }
+
+    #[cfg(feature = "sqs")]
+    async fn send_message(replay: &StaticReplayClient, intercept: impl Intercept + 'static) {
+        let client = aws_sdk_sqs::Client::from_conf(
+            aws_sdk_sqs::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(Credentials::new(
+                    "ATESTCLIENT",
+                    "astestsecretkey",
+                    None,
+                    None,
+                    "test-credentials",
+                ))
+                .region(Region::new("us-east-1"))
+                .retry_config(
+                    RetryConfig::standard()
+                        .with_max_attempts(2)
+                        .with_initial_backoff(Duration::from_millis(1)),
+                )
+                .http_client(replay.clone())
+                .build(),
+        );
+        client
+            .send_message()
+            .queue_url("https://sqs.us-east-1.amazonaws.com/123456789012/queue")
+            .message_body("hello")
+            .customize()
+            .interceptor(intercept)
+            .send()
+            .await
+            .unwrap();
+    }
+
+    #[cfg(feature = "sqs")]
+    fn message_trace_header(
+        request: &aws_smithy_runtime_api::client::orchestrator::HttpRequest,
+    ) -> Header {
+        let body: serde_json::Value =
+            serde_json::from_slice(request.body().bytes().unwrap()).unwrap();
+        body["MessageSystemAttributes"]["AWSTraceHeader"]["StringValue"]
+            .as_str()
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[cfg(feature = "sqs")]
+    #[tokio::test]
+    async fn intercept_operation_should_inject_first_attempt_into_messages() {
+        let recorder = RecordingClient::default();
+        let context = context(&recorder);
+        let replay =
+            StaticReplayClient::new(vec![throttled_event(), event(200, r#"{"MessageId":"m1"}"#)]);
+        send_message(&replay, context.intercept_operation("SQS", "SendMessage")).await;
+        let documents = recorder.documents();
+        assert_eq!(documents.len(), 2);
+        let requests = replay.actual_requests().collect::<Vec<_>>();
+        assert_eq!(requests.len(), 2);
+        for request in requests {
+            let header = message_trace_header(request);
+            assert_eq!(header.trace_id.to_string(), documents[0]["trace_id"]);
+            assert_eq!(
+                header.parent_id.unwrap().to_string(),
+                documents[0]["id"].as_str().unwrap(),
+            );
+        }
+    }
+
+    #[cfg(feature = "sqs")]
+    #[tokio::test]
+    async fn intercept_operation_with_parent_should_inject_operation_into_messages() {
+        let recorder = RecordingClient::default();
+        let context = context(&recorder);
+        let replay = StaticReplayClient::new(vec![event(200, r#"{"MessageId":"m1"}"#)]);
+        send_message(
+            &replay,
+            context.intercept_operation_with_parent("SQS", "SendMessage"),
+        )
+        .await;
+        let documents = recorder.documents();
+        assert_eq!(documents.len(), 2);
+        let request = replay.actual_requests().next().unwrap();
+        assert_eq!(
+            message_trace_header(request).parent_id.unwrap().to_string(),
+            documents[1]["id"].as_str().unwrap(),
+        );
+    }
+
+    #[test]
+    fn is_throttled_should_detect_error_codes() {
+        fn response(status: u16, error_type: Option<&str>, body: &str) -> HttpResponse {
+            let mut response = http::Response::builder().status(status);
+            if let Some(error_type) = error_type {
+                response = response.header("x-amzn-ErrorType", error_type);
+            }
+            HttpResponse::try_from(response.body(SdkBody::from(body)).unwrap()).unwrap()
+        }
+        assert!(is_throttled(&response(429, None, "")));
+        assert!(is_throttled(&response(
+            400,
+            Some("ThrottlingException:http://internal.amazon.com/coral/com.amazon.coral.availability/"),
+            "",
+        )));
+        assert!(is_throttled(&response(
+            503,
+            None,
+            "<Error><Code>SlowDown</Code><Message>Please reduce your request rate.</Message></Error>",
+        )));
+        assert!(!is_throttled(&response(
+            400,
+            Some("ValidationException"),
+            r#"{"__type":"com.amazonaws.dynamodb.v20120810#ValidationException"}"#,
+        )));
+        assert!(!is_throttled(&response(200, None, "Throttling")));
+    }
+}
@@ -0,0 +1,170 @@
+//! Trace header propagation through Amazon SNS messages.
+//!
+//! The trace header is carried in the `AWSTraceHeader` message attribute.
+//! [`ContextExt::intercept_operation`][crate::ContextExt::intercept_operation]
+//! injects it into `Publish` and `PublishBatch` requests; the functions in
+//! this module are for requests built without the interceptor.
+
+use std::collections::HashMap;
+
+use aws_sdk_sns::operation::publish::PublishInput;
+use aws_sdk_sns::operation::publish_batch::PublishBatchInput;
+use aws_sdk_sns::types::MessageAttributeValue;
+use aws_smithy_runtime_api::client::interceptors::context::Input;
+use xray_lite::{report_error, Error, Header};
+
+/// Maximum number of message attributes of an SNS message.
+const MAX_MESSAGE_ATTRIBUTES: usize = 10;
+
+/// Injects a trace header into a `Publish` request.
+///
+/// An existing `AWSTraceHeader` attribute is kept. The trace header is not
+/// injected into a message that already has the maximum 10 attributes.
+pub fn inject_publish(input: &mut PublishInput, header: &Header) {
+    inject(&mut input.message_attributes, header);
+}
+
+/// Injects a trace header into every entry of a `PublishBatch` request.
+///
+/// An existing `AWSTraceHeader` attribute is kept. The trace header is not
+/// injected into an entry that already has the maximum 10 attributes.
+pub fn inject_publish_batch(input: &mut PublishBatchInput, header: &Header) {
+    for entry in input.publish_batch_request_entries.iter_mut().flatten() {
+        inject(&mut entry.message_attributes, header);
+    }
+}
+
+/// Injects a trace header into an SNS request if it publishes messages.
+pub(crate) fn inject_input(input: &mut Input, header: &Header) {
+    if let Some(input) = input.downcast_mut::<PublishInput>() {
+        inject_publish(input, header);
+    } else if let Some(input) = input.downcast_mut::<PublishBatchInput>() {
+        inject_publish_batch(input, header);
+    }
+}
+
+fn inject(attributes: &mut Option<HashMap<String, MessageAttributeValue>>, header: &Header) {
+    let attributes = attributes.get_or_insert_with(HashMap::new);
+    if attributes.contains_key(Header::MESSAGE_ATTRIBUTE) {
+        return;
+    }
+    // SNS rejects the message rather than ignoring the extra attribute
+    if attributes.len() >= MAX_MESSAGE_ATTRIBUTES {
+        report_error(
+            "failed to inject trace header into SNS message",
+            &Error::IO(std::io::Error::other(format!(
+                "message already has {} attributes",
+                attributes.len()
+            ))),
+        );
+        return;
+    }
+    let value = MessageAttributeValue::builder()
+        .data_type("String")
+        .string_value(header.to_string())
+        .build();
+    if let Ok(value) = value {
+        attributes.insert(Header::MESSAGE_ATTRIBUTE.to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_sns::types::PublishBatchRequestEntry;
+
+    use super::*;
+
+    fn header() -> Header {
+        "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1"
+            .parse()
+            .unwrap()
+    }
+
+    fn trace_header(attributes: &Option<HashMap<String, MessageAttributeValue>>) -> Option<&str> {
+        attributes
+            .as_ref()?
+            .get(Header::MESSAGE_ATTRIBUTE)?
+            .string_value()
+    }
+
+    #[test]
+    fn inject_input_should_set_trace_header_of_publish() {
+        let header = header();
+        let mut input = Input::erase(
+            PublishInput::builder()
+                .topic_arn("arn:aws:sns:us-east-1:123456789012:topic")
+                .message("hello")
+                .build()
+                .unwrap(),
+        );
+        inject_input(&mut input, &header);
+        let input = input.downcast_ref::<PublishInput>().unwrap();
+        let attributes = input.message_attributes.as_ref().unwrap();
+        assert_eq!(
+            attributes["AWSTraceHeader"].string_value(),
+            Some(header.to_string().as_str()),
+        );
+    }
+
+    #[test]
+    fn inject_publish_batch_should_keep_existing_trace_header() {
+        let existing = MessageAttributeValue::builder()
+            .data_type("String")
+            .string_value("Root=1-65dfb5a1-0123456789abcdef01234567")
+            .build()
+            .unwrap();
+        let mut input = PublishBatchInput::builder()
+            .topic_arn("arn:aws:sns:us-east-1:123456789012:topic")
+            .publish_batch_request_entries(
+                PublishBatchRequestEntry::builder()
+                    .id("1")
+                    .message("one")
+                    .message_attributes(Header::MESSAGE_ATTRIBUTE, existing)
+                    .build()
+                    .unwrap(),
+            )
+            .publish_batch_request_entries(
+                PublishBatchRequestEntry::builder()
+                    .id("2")
+                    .message("two")
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        inject_publish_batch(&mut input, &header());
+        let entries = input.publish_batch_request_entries.unwrap();
+        assert_eq!(
+            trace_header(&entries[0].message_attributes),
+            Some("Root=1-65dfb5a1-0123456789abcdef01234567"),
+        );
+        assert_eq!(
+            trace_header(&entries[1].message_attributes),
+            Some(header().to_string().as_str()),
+        );
+    }
+
+    #[test]
+    fn inject_publish_should_skip_message_with_maximum_attributes() {
+        let mut builder = PublishInput::builder()
+            .topic_arn("arn:aws:sns:us-east-1:123456789012:topic")
+            .message("hello");
+        for i in 0..MAX_MESSAGE_ATTRIBUTES {
+            builder = builder.message_attributes(
+                format!("attr{i}"),
+                MessageAttributeValue::builder()
+                    .data_type("String")
+                    .string_value("value")
+                    .build()
+                    .unwrap(),
+            );
+        }
+        let mut input = builder.build().unwrap();
+        inject_publish(&mut input, &header());
+        assert_eq!(trace_header(&input.message_attributes), None);
+        assert_eq!(
+            input.message_attributes.unwrap().len(),
+            MAX_MESSAGE_ATTRIBUTES
+        );
+    }
+}
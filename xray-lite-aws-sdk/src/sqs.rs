@@ -0,0 +1,166 @@
+//! Trace header propagation through Amazon SQS messages.
+//!
+//! The trace header is carried in the `AWSTraceHeader` system attribute of a
+//! message. [`ContextExt::intercept_operation`][crate::ContextExt::intercept_operation]
+//! injects it into `SendMessage` and `SendMessageBatch` requests; the
+//! functions in this module are for requests built without the interceptor.
+
+use std::collections::HashMap;
+
+use aws_sdk_sqs::operation::send_message::SendMessageInput;
+use aws_sdk_sqs::operation::send_message_batch::SendMessageBatchInput;
+use aws_sdk_sqs::types::{
+    Message, MessageSystemAttributeName, MessageSystemAttributeNameForSends,
+    MessageSystemAttributeValue,
+};
+use aws_smithy_runtime_api::client::interceptors::context::Input;
+use xray_lite::Header;
+
+/// Injects a trace header into a `SendMessage` request.
+///
+/// An existing `AWSTraceHeader` attribute is kept.
+pub fn inject_send_message(input: &mut SendMessageInput, header: &Header) {
+    inject(&mut input.message_system_attributes, header);
+}
+
+/// Injects a trace header into every entry of a `SendMessageBatch` request.
+///
+/// An existing `AWSTraceHeader` attribute is kept.
+pub fn inject_send_message_batch(input: &mut SendMessageBatchInput, header: &Header) {
+    for entry in input.entries.iter_mut().flatten() {
+        inject(&mut entry.message_system_attributes, header);
+    }
+}
+
+/// Parses the trace header of a received message.
+///
+/// The message has to be received with the `AWSTraceHeader` system
+/// attribute requested. `None` if the message has no trace header.
+pub fn message_header(message: &Message) -> Option<xray_lite::Result<Header>> {
+    message
+        .attributes
+        .as_ref()?
+        .get(&MessageSystemAttributeName::AwsTraceHeader)
+        .map(|value| value.parse())
+}
+
+/// Injects a trace header into an SQS request if it sends messages.
+pub(crate) fn inject_input(input: &mut Input, header: &Header) {
+    if let Some(input) = input.downcast_mut::<SendMessageInput>() {
+        inject_send_message(input, header);
+    } else if let Some(input) = input.downcast_mut::<SendMessageBatchInput>() {
+        inject_send_message_batch(input, header);
+    }
+}
+
+fn inject(
+    attributes: &mut Option<
+        HashMap<MessageSystemAttributeNameForSends, MessageSystemAttributeValue>,
+    >,
+    header: &Header,
+) {
+    let attributes = attributes.get_or_insert_with(HashMap::new);
+    if attributes.contains_key(&MessageSystemAttributeNameForSends::AwsTraceHeader) {
+        return;
+    }
+    let value = MessageSystemAttributeValue::builder()
+        .data_type("String")
+        .string_value(header.to_string())
+        .build();
+    if let Ok(value) = value {
+        attributes.insert(MessageSystemAttributeNameForSends::AwsTraceHeader, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_sqs::types::SendMessageBatchRequestEntry;
+
+    use super::*;
+
+    fn header() -> Header {
+        "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1"
+            .parse()
+            .unwrap()
+    }
+
+    fn trace_header(
+        attributes: &Option<
+            HashMap<MessageSystemAttributeNameForSends, MessageSystemAttributeValue>,
+        >,
+    ) -> Option<&str> {
+        attributes
+            .as_ref()?
+            .get(&MessageSystemAttributeNameForSends::AwsTraceHeader)?
+            .string_value()
+    }
+
+    #[test]
+    fn inject_input_should_set_trace_header_of_send_message() {
+        let mut input = Input::erase(
+            SendMessageInput::builder()
+                .queue_url("https://sqs.us-east-1.amazonaws.com/123456789012/queue")
+                .message_body("hello")
+                .build()
+                .unwrap(),
+        );
+        inject_input(&mut input, &header());
+        let input = input.downcast_ref::<SendMessageInput>().unwrap();
+        assert_eq!(
+            trace_header(&input.message_system_attributes),
+            Some(header().to_string().as_str()),
+        );
+    }
+
+    #[test]
+    fn inject_send_message_batch_should_keep_existing_trace_header() {
+        let existing = MessageSystemAttributeValue::builder()
+            .data_type("String")
+            .string_value("Root=1-65dfb5a1-0123456789abcdef01234567")
+            .build()
+            .unwrap();
+        let mut input = SendMessageBatchInput::builder()
+            .entries(
+                SendMessageBatchRequestEntry::builder()
+                    .id("1")
+                    .message_body("one")
+                    .message_system_attributes(
+                        MessageSystemAttributeNameForSends::AwsTraceHeader,
+                        existing,
+                    )
+                    .build()
+                    .unwrap(),
+            )
+            .entries(
+                SendMessageBatchRequestEntry::builder()
+                    .id("2")
+                    .message_body("two")
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        inject_send_message_batch(&mut input, &header());
+        let entries = input.entries.unwrap();
+        assert_eq!(
+            trace_header(&entries[0].message_system_attributes),
+            Some("Root=1-65dfb5a1-0123456789abcdef01234567"),
+        );
+        assert_eq!(
+            trace_header(&entries[1].message_system_attributes),
+            Some(header().to_string().as_str()),
+        );
+    }
+
+    #[test]
+    fn message_header_should_parse_trace_header() {
+        let message = Message::builder()
+            .attributes(
+                MessageSystemAttributeName::AwsTraceHeader,
+                header().to_string(),
+            )
+            .build();
+        assert_eq!(message_header(&message).unwrap().unwrap(), header());
+        assert!(message_header(&Message::builder().build()).is_none());
+    }
+}
@@ -0,0 +1,46 @@
+//! Daemon framing of packets.
+
+use serde_json::Value;
+
+/// Parses a packet in the daemon framing.
+///
+/// A packet consists of the `{"format": "json", "version": 1}` header, a
+/// newline, and a segment document.
+pub fn parse_packet(packet: &[u8]) -> Result<Value, String> {
+    let newline = packet
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or("missing header")?;
+    let (header, document) = packet.split_at(newline);
+    let header: Value =
+        serde_json::from_slice(header).map_err(|e| format!("invalid header: {e}"))?;
+    if header.get("format").and_then(Value::as_str) != Some("json")
+        || header.get("version").and_then(Value::as_u64) != Some(1)
+    {
+        return Err(format!("unsupported header: {header}"));
+    }
+    serde_json::from_slice(&document[1..]).map_err(|e| format!("invalid document: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parse_packet_should_parse_document() {
+        assert_eq!(
+            parse_packet(b"{\"format\": \"json\", \"version\": 1}\n{\"id\":\"53995c3f42cd8ad8\"}")
+                .unwrap(),
+            json!({"id": "53995c3f42cd8ad8"})
+        );
+    }
+
+    #[test]
+    fn parse_packet_should_reject_missing_or_unsupported_header() {
+        assert!(parse_packet(b"{\"id\":\"53995c3f42cd8ad8\"}").is_err());
+        assert!(parse_packet(b"{\"format\": \"json\", \"version\": 2}\n{}").is_err());
+        assert!(parse_packet(b"{\"format\": \"json\", \"version\": 1}\nnot json").is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Local stand-in for the AWS X-Ray daemon.
+//!
+//! Listens for segment documents in the daemon framing, reassembles them into
+//! traces, and prints an ASCII waterfall of each trace once no document for
+//! it has arrived for a while.
+//!
+//! ```text
+//! xray-lite-devd [--listen ADDRESS] [--idle SECONDS]
+//! ```
+
+use std::io::Write as _;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+mod framing;
+mod store;
+mod waterfall;
+
+use crate::framing::parse_packet;
+use crate::store::Store;
+
+/// Default address to listen on.
+const DEFAULT_LISTEN: &str = "127.0.0.1:2000";
+
+/// Default idle time after which a trace is printed.
+const DEFAULT_IDLE: Duration = Duration::from_secs(2);
+
+/// Maximum size of a packet.
+const MAX_PACKET_SIZE: usize = 64 * 1024;
+
+const USAGE: &str = "usage: xray-lite-devd [--listen ADDRESS] [--idle SECONDS]
+
+Listens for X-Ray segment documents on UDP and prints each trace as a
+waterfall once no document for it has arrived for the idle time.
+
+options:
+    --listen ADDRESS  address to listen on (default: 127.0.0.1:2000)
+    --idle SECONDS    idle time before a trace is printed (default: 2)";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    listen: String,
+    idle: Duration,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        listen: DEFAULT_LISTEN.to_string(),
+        idle: DEFAULT_IDLE,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => {
+                options.listen = args.next().ok_or("--listen requires an address")?;
+            }
+            "--idle" => {
+                let seconds: f64 = args
+                    .next()
+                    .ok_or("--idle requires seconds")?
+                    .parse()
+                    .map_err(|e| format!("invalid --idle: {e}"))?;
+                options.idle = Duration::try_from_secs_f64(seconds)
+                    .map_err(|e| format!("invalid --idle: {e}"))?;
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => return Err(format!("unknown argument: {arg}\n\n{USAGE}")),
+        }
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = run(&options) {
+        eprintln!("xray-lite-devd: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(&options.listen)?;
+    socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+    eprintln!("xray-lite-devd: listening on udp:{}", socket.local_addr()?);
+    let mut store = Store::default();
+    let mut buf = vec![0u8; MAX_PACKET_SIZE];
+    let stdout = std::io::stdout();
+    loop {
+        match socket.recv(&mut buf) {
+            Ok(size) => {
+                let result = parse_packet(&buf[..size])
+                    .and_then(|document| store.add(&document, Instant::now()));
+                if let Err(e) = result {
+                    eprintln!("xray-lite-devd: dropped packet: {e}");
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => return Err(e),
+        }
+        for (trace_id, trace) in store.take_idle(Instant::now(), options.idle) {
+            let mut out = stdout.lock();
+            waterfall::render(&trace_id, &trace, &mut out)?;
+            writeln!(out)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn parse_args_should_apply_defaults() {
+        assert_eq!(
+            parse_args(args(&[])).unwrap(),
+            Options {
+                listen: "127.0.0.1:2000".into(),
+                idle: Duration::from_secs(2),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_options() {
+        assert_eq!(
+            parse_args(args(&["--listen", "0.0.0.0:3000", "--idle", "0.5"])).unwrap(),
+            Options {
+                listen: "0.0.0.0:3000".into(),
+                idle: Duration::from_millis(500),
+            }
+        );
+        assert!(parse_args(args(&["--idle", "-1"])).is_err());
+        assert!(parse_args(args(&["--verbose"])).is_err());
+    }
+
+    #[test]
+    fn devd_should_assemble_documents_from_daemon_client() {
+        use xray_lite::{
+            Client as _, Context as _, CustomNamespace, DaemonClient, SubsegmentContext,
+        };
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let client = DaemonClient::new(socket.local_addr().unwrap()).unwrap();
+        let context = SubsegmentContext::with_header(
+            client.clone(),
+            "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1"
+                .parse()
+                .unwrap(),
+        );
+        drop(context.enter_subsegment(CustomNamespace::new("work")));
+        client
+            .send(&serde_json::json!({"not": "a document"}))
+            .unwrap();
+
+        let mut store = Store::default();
+        let mut buf = vec![0u8; MAX_PACKET_SIZE];
+        let mut errors = 0;
+        for _ in 0..3 {
+            let size = socket.recv(&mut buf).unwrap();
+            if parse_packet(&buf[..size])
+                .and_then(|document| store.add(&document, Instant::now()))
+                .is_err()
+            {
+                errors += 1;
+            }
+        }
+        assert_eq!(errors, 1);
+        let traces = store.take_idle(Instant::now(), Duration::ZERO);
+        assert_eq!(traces.len(), 1);
+        let (_, trace) = &traces[0];
+        assert_eq!(trace.nodes.len(), 1);
+        assert_eq!(trace.nodes[0].name, "work");
+        assert!(!trace.nodes[0].is_dangling());
+    }
+}
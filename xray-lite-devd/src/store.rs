@@ -0,0 +1,269 @@
+//! Reassembly of documents into traces.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+/// Segment or subsegment in a trace.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Node {
+    /// Segment or subsegment ID.
+    pub id: String,
+    /// ID of the parent; `None` for a segment.
+    pub parent_id: Option<String>,
+    /// Name.
+    pub name: String,
+    /// Start time in seconds.
+    pub start_time: f64,
+    /// End time in seconds; `None` while in progress.
+    pub end_time: Option<f64>,
+    /// Whether a fault occurred.
+    pub fault: bool,
+    /// Whether an error occurred.
+    pub error: bool,
+    /// Whether the request was throttled.
+    pub throttle: bool,
+    /// Annotations rendered as `key=value` sorted by key.
+    pub annotations: Vec<String>,
+    /// Exception messages.
+    pub exceptions: Vec<String>,
+}
+
+impl Node {
+    /// Returns whether the node has never been closed.
+    pub fn is_dangling(&self) -> bool {
+        self.end_time.is_none()
+    }
+}
+
+/// Trace reassembled from documents.
+#[derive(Debug)]
+pub struct Trace {
+    /// Nodes in the order they were first received.
+    pub nodes: Vec<Node>,
+    last_update: Instant,
+}
+
+impl Trace {
+    fn upsert(&mut self, node: Node) {
+        match self.nodes.iter_mut().find(|n| n.id == node.id) {
+            // an in-progress document never overrides a completed one
+            Some(existing) if !existing.is_dangling() && node.is_dangling() => {}
+            Some(existing) => *existing = node,
+            None => self.nodes.push(node),
+        }
+    }
+
+    /// Returns the children of a given node sorted by start time.
+    ///
+    /// Returns the roots if `parent_id` is `None`.
+    pub fn children(&self, parent_id: Option<&str>) -> Vec<&Node> {
+        let mut children: Vec<&Node> = self
+            .nodes
+            .iter()
+            .filter(|node| match parent_id {
+                Some(parent_id) => node.parent_id.as_deref() == Some(parent_id),
+                // nodes whose parent has not been received are roots
+                None => match &node.parent_id {
+                    Some(parent_id) => !self.nodes.iter().any(|n| &n.id == parent_id),
+                    None => true,
+                },
+            })
+            .collect();
+        children.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        children
+    }
+}
+
+/// Store of traces being reassembled.
+#[derive(Debug, Default)]
+pub struct Store {
+    traces: HashMap<String, Trace>,
+}
+
+impl Store {
+    /// Adds a document.
+    pub fn add(&mut self, document: &Value, now: Instant) -> Result<(), String> {
+        let trace_id = document
+            .get("trace_id")
+            .and_then(Value::as_str)
+            .ok_or("missing trace_id")?;
+        let trace = self
+            .traces
+            .entry(trace_id.to_string())
+            .or_insert_with(|| Trace {
+                nodes: Vec::new(),
+                last_update: now,
+            });
+        trace.last_update = now;
+        let parent_id = document
+            .get("parent_id")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        add_node(trace, document, parent_id)
+    }
+
+    /// Removes and returns the traces not updated for `idle`.
+    pub fn take_idle(&mut self, now: Instant, idle: Duration) -> Vec<(String, Trace)> {
+        let ids: Vec<String> = self
+            .traces
+            .iter()
+            .filter(|(_, trace)| now.duration_since(trace.last_update) >= idle)
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| self.traces.remove_entry(&id))
+            .collect()
+    }
+}
+
+fn add_node(trace: &mut Trace, document: &Value, parent_id: Option<String>) -> Result<(), String> {
+    let id = document
+        .get("id")
+        .and_then(Value::as_str)
+        .ok_or("missing id")?
+        .to_string();
+    let flag = |key| document.get(key).and_then(Value::as_bool).unwrap_or(false);
+    let mut annotations: Vec<String> = document
+        .get("annotations")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| match value {
+            Value::String(value) => format!("{key}={value}"),
+            value => format!("{key}={value}"),
+        })
+        .collect();
+    annotations.sort();
+    let exceptions = document
+        .pointer("/cause/exceptions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|exception| exception.get("message").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect();
+    let in_progress = flag("in_progress");
+    trace.upsert(Node {
+        id: id.clone(),
+        parent_id,
+        name: document
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        start_time: document
+            .get("start_time")
+            .and_then(Value::as_f64)
+            .unwrap_or_default(),
+        end_time: document
+            .get("end_time")
+            .and_then(Value::as_f64)
+            .filter(|_| !in_progress),
+        fault: flag("fault"),
+        error: flag("error"),
+        throttle: flag("throttle"),
+        annotations,
+        exceptions,
+    });
+    for subsegment in document
+        .get("subsegments")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        add_node(trace, subsegment, Some(id.clone()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const TRACE_ID: &str = "1-65dfb5a1-0123456789abcdef01234567";
+
+    #[test]
+    fn store_should_replace_in_progress_with_completed() {
+        let now = Instant::now();
+        let mut store = Store::default();
+        let in_progress = json!({
+            "trace_id": TRACE_ID,
+            "id": "0000000000000001",
+            "name": "work",
+            "start_time": 1.0,
+            "in_progress": true,
+        });
+        let completed = json!({
+            "trace_id": TRACE_ID,
+            "id": "0000000000000001",
+            "name": "work",
+            "start_time": 1.0,
+            "end_time": 2.0,
+        });
+        store.add(&in_progress, now).unwrap();
+        store.add(&completed, now).unwrap();
+        // a late in-progress document is ignored
+        store.add(&in_progress, now).unwrap();
+        let traces = store.take_idle(now, Duration::ZERO);
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].1.nodes.len(), 1);
+        assert_eq!(traces[0].1.nodes[0].end_time, Some(2.0));
+    }
+
+    #[test]
+    fn store_should_flatten_embedded_subsegments() {
+        let now = Instant::now();
+        let mut store = Store::default();
+        store
+            .add(
+                &json!({
+                    "trace_id": TRACE_ID,
+                    "id": "0000000000000001",
+                    "parent_id": "fffffffffffffff0",
+                    "start_time": 1.0,
+                    "end_time": 2.0,
+                    "subsegments": [{"id": "0000000000000002", "start_time": 1.5, "end_time": 1.6}],
+                }),
+                now,
+            )
+            .unwrap();
+        let (_, trace) = store.take_idle(now, Duration::ZERO).pop().unwrap();
+        let roots = trace.children(None);
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].id, "0000000000000001");
+        let children = trace.children(Some("0000000000000001"));
+        assert_eq!(children[0].id, "0000000000000002");
+    }
+
+    #[test]
+    fn store_should_keep_active_traces() {
+        let now = Instant::now();
+        let mut store = Store::default();
+        store
+            .add(
+                &json!({"trace_id": TRACE_ID, "id": "0000000000000001", "start_time": 1.0}),
+                now,
+            )
+            .unwrap();
+        assert!(store.take_idle(now, Duration::from_secs(1)).is_empty());
+        assert_eq!(
+            store
+                .take_idle(now + Duration::from_secs(1), Duration::from_secs(1))
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn store_should_reject_documents_without_ids() {
+        let mut store = Store::default();
+        assert!(store.add(&json!({"id": "1"}), Instant::now()).is_err());
+        assert!(store
+            .add(&json!({"trace_id": TRACE_ID}), Instant::now())
+            .is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! ASCII waterfall of a trace.
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::store::{Node, Trace};
+
+/// Width of the timeline in characters.
+const TIMELINE_WIDTH: usize = 40;
+
+/// Writes the waterfall of a trace.
+///
+/// Each line shows a segment or subsegment indented under its parent, its
+/// duration, its position in the timeline, and its errors and annotations.
+/// Never-closed subsegments are drawn with `~` up to the end of the trace and
+/// marked as dangling.
+pub fn render(trace_id: &str, trace: &Trace, out: &mut impl Write) -> io::Result<()> {
+    let mut rows = Vec::new();
+    let mut visited = HashSet::new();
+    collect_rows(trace, None, 0, &mut visited, &mut rows);
+    // nodes in a cycle of parent IDs have no root; each cycle is drawn from
+    // the node received first
+    for node in &trace.nodes {
+        if visited.insert(node.id.as_str()) {
+            rows.push((0, node));
+            collect_rows(trace, Some(&node.id), 1, &mut visited, &mut rows);
+        }
+    }
+    let start = rows
+        .iter()
+        .map(|(_, node)| node.start_time)
+        .fold(f64::INFINITY, f64::min);
+    let end = rows
+        .iter()
+        .map(|(_, node)| node.end_time.unwrap_or(node.start_time))
+        .fold(f64::NEG_INFINITY, f64::max);
+    if rows.is_empty() {
+        return writeln!(out, "trace {trace_id} (empty)");
+    }
+    writeln!(out, "trace {trace_id} {}", format_duration(end - start))?;
+    let name_width = rows
+        .iter()
+        .map(|(depth, node)| depth * 2 + node.name.chars().count())
+        .max()
+        .unwrap_or_default();
+    for (depth, node) in rows {
+        let duration = match node.end_time {
+            Some(end_time) => format_duration(end_time - node.start_time),
+            None => "in progress".to_string(),
+        };
+        let mut line = format!(
+            "  {:indent$}{:<width$} {:>11} |{}|",
+            "",
+            node.name,
+            duration,
+            timeline(node, start, end),
+            indent = depth * 2,
+            width = name_width - depth * 2,
+        );
+        for label in labels(node) {
+            line.push(' ');
+            line.push_str(&label);
+        }
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+fn collect_rows<'a>(
+    trace: &'a Trace,
+    parent_id: Option<&str>,
+    depth: usize,
+    visited: &mut HashSet<&'a str>,
+    rows: &mut Vec<(usize, &'a Node)>,
+) {
+    for node in trace.children(parent_id) {
+        // a node is drawn only once even if parent IDs make a cycle
+        if visited.insert(node.id.as_str()) {
+            rows.push((depth, node));
+            collect_rows(trace, Some(&node.id), depth + 1, visited, rows);
+        }
+    }
+}
+
+fn timeline(node: &Node, start: f64, end: f64) -> String {
+    let span = (end - start).max(f64::EPSILON);
+    let position = |time: f64| {
+        let position = (time - start) / span * TIMELINE_WIDTH as f64;
+        // rounds off floating point errors before taking floor or ceil
+        ((position * 1e6).round() / 1e6).clamp(0.0, TIMELINE_WIDTH as f64)
+    };
+    let from = position(node.start_time).floor() as usize;
+    let to = (position(node.end_time.unwrap_or(end)).ceil() as usize)
+        .max(from + 1)
+        .min(TIMELINE_WIDTH);
+    let from = from.min(to - 1);
+    let bar = if node.is_dangling() { '~' } else { '#' };
+    (0..TIMELINE_WIDTH)
+        .map(|i| if (from..to).contains(&i) { bar } else { ' ' })
+        .collect()
+}
+
+fn labels(node: &Node) -> Vec<String> {
+    let mut labels = Vec::new();
+    if node.is_dangling() {
+        labels.push("dangling".to_string());
+    }
+    for (flag, label) in [
+        (node.fault, "fault"),
+        (node.error, "error"),
+        (node.throttle, "throttle"),
+    ] {
+        if flag {
+            labels.push(label.to_string());
+        }
+    }
+    labels.extend(node.annotations.iter().cloned());
+    labels.extend(node.exceptions.iter().map(|message| format!("!{message}")));
+    labels
+}
+
+fn format_duration(seconds: f64) -> String {
+    if seconds < 1.0 {
+        format!("{:.1}ms", seconds * 1000.0)
+    } else {
+        format!("{seconds:.2}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::store::Store;
+
+    #[test]
+    fn render_should_draw_waterfall() {
+        let now = Instant::now();
+        let mut store = Store::default();
+        for document in [
+            json!({
+                "trace_id": "1-65dfb5a1-0123456789abcdef01234567",
+                "id": "0000000000000001",
+                "parent_id": "fffffffffffffff0",
+                "name": "handler",
+                "start_time": 100.0,
+                "end_time": 100.4,
+                "annotations": {"user": "alice"},
+            }),
+            json!({
+                "trace_id": "1-65dfb5a1-0123456789abcdef01234567",
+                "id": "0000000000000002",
+                "parent_id": "0000000000000001",
+                "name": "S3",
+                "start_time": 100.1,
+                "end_time": 100.2,
+                "fault": true,
+                "cause": {"exceptions": [{"id": "0000000000000003", "message": "boom"}]},
+            }),
+            json!({
+                "trace_id": "1-65dfb5a1-0123456789abcdef01234567",
+                "id": "0000000000000004",
+                "parent_id": "0000000000000001",
+                "name": "stuck",
+                "start_time": 100.3,
+                "in_progress": true,
+            }),
+        ] {
+            store.add(&document, now).unwrap();
+        }
+        let (trace_id, trace) = store.take_idle(now, Duration::ZERO).pop().unwrap();
+        let mut out = Vec::new();
+        render(&trace_id, &trace, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                "trace 1-65dfb5a1-0123456789abcdef01234567 400.0ms",
+                "  handler     400.0ms |########################################| user=alice",
+                "    S3        100.0ms |          ##########                    | fault !boom",
+                "    stuck in progress |                              ~~~~~~~~~~| dangling",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn render_should_draw_cycle_of_parent_ids_once() {
+        let now = Instant::now();
+        let mut store = Store::default();
+        for (id, parent_id, start_time) in [
+            ("0000000000000001", "0000000000000002", 100.0),
+            ("0000000000000002", "0000000000000001", 100.1),
+        ] {
+            let document = json!({
+                "trace_id": "1-65dfb5a1-0123456789abcdef01234567",
+                "id": id,
+                "parent_id": parent_id,
+                "name": id,
+                "start_time": start_time,
+                "end_time": 100.2,
+            });
+            store.add(&document, now).unwrap();
+        }
+        let (trace_id, trace) = store.take_idle(now, Duration::ZERO).pop().unwrap();
+        let mut out = Vec::new();
+        render(&trace_id, &trace, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("  0000000000000001 "));
+        assert!(lines[2].starts_with("    0000000000000002 "));
+    }
+}
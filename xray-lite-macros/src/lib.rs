@@ -0,0 +1,247 @@
+#![warn(missing_docs)]
+//! Attribute macros for [`xray-lite`](https://docs.rs/xray-lite).
+//!
+//! Use them through the `macros` feature of `xray-lite` rather than depending
+//! on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens as _};
+use syn::parse::Parser as _;
+use syn::punctuated::Punctuated;
+use syn::{
+    parse_macro_input, Expr, Ident, ItemFn, Lit, Meta, ReturnType, Token, Type, TypeImplTrait,
+};
+
+/// Traces a function in a custom subsegment.
+///
+/// The subsegment is entered in the context given by the `context` argument
+/// and named after the function. The name is prefixed in the same way as any
+/// [`CustomNamespace`](https://docs.rs/xray-lite/latest/xray_lite/struct.CustomNamespace.html);
+/// i.e., by [`SubsegmentContext::with_name_prefix`](https://docs.rs/xray-lite/latest/xray_lite/struct.SubsegmentContext.html#method.with_name_prefix).
+/// Subsegments entered while the function runs become its children. If the
+/// function returns `Err`, the subsegment is marked as a fault and the error
+/// is recorded in the cause.
+///
+/// The return type is regarded as a `Result` if its last path segment is
+/// named `Result`; e.g., `std::io::Result<T>`. Give the `err` argument for
+/// any other alias of `Result`.
+///
+/// Both sync and async functions are supported.
+///
+/// Arguments:
+/// - `context = <expr>`: context to enter the subsegment in; e.g., `self.context`.
+///   The function argument `context` by default.
+///   An async function needs a context that is `Clone`, or a reference.
+/// - `name = "<name>"`: name of the subsegment instead of the function name.
+/// - `annotations(<arg>, ...)`: function arguments to record as annotations.
+///   They have to be `Clone` and convertible into `Annotation`.
+/// - `metadata(<arg>, ...)`: function arguments to record as metadata.
+///   They have to be `Serialize`.
+/// - `err`: records an `Err` returned from the function even if the return
+///   type is not named `Result`. The error has to be `Display`.
+///
+/// ```
+/// use xray_lite::{traced, Context, DaemonClient, SubsegmentContext};
+///
+/// #[traced(annotations(user_id), metadata(items))]
+/// fn put_order(
+///     context: &impl Context,
+///     user_id: &str,
+///     items: &[String],
+/// ) -> Result<(), std::io::Error> {
+///     // ...
+///     Ok(())
+/// }
+///
+/// # std::env::set_var("AWS_XRAY_DAEMON_ADDRESS", "127.0.0.1:2000");
+/// let client = DaemonClient::from_lambda_env().unwrap();
+/// # std::env::set_var("_X_AMZN_TRACE_ID", "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1");
+/// let context = SubsegmentContext::from_lambda_env(client).unwrap();
+/// put_order(&context, "u-123", &["apple".to_string()]).unwrap();
+/// ```
+#[proc_macro_attribute]
+pub fn traced(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = match Punctuated::<Meta, Token![,]>::parse_terminated.parse(args) {
+        Ok(args) => args,
+        Err(e) => return e.into_compile_error().into(),
+    };
+    let item = parse_macro_input!(item as ItemFn);
+    match expand(args, item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
+/// Arguments of [`traced`].
+struct TracedArgs {
+    context: Expr,
+    name: Option<String>,
+    annotations: Vec<Ident>,
+    metadata: Vec<Ident>,
+    err: bool,
+}
+
+impl TracedArgs {
+    fn parse(args: Punctuated<Meta, Token![,]>) -> syn::Result<Self> {
+        let mut context = None;
+        let mut name = None;
+        let mut annotations = Vec::new();
+        let mut metadata = Vec::new();
+        let mut err = false;
+        for arg in args {
+            match &arg {
+                Meta::Path(path) if path.is_ident("err") => err = true,
+                Meta::NameValue(arg) if arg.path.is_ident("context") => {
+                    context = Some(arg.value.clone());
+                }
+                Meta::NameValue(arg) if arg.path.is_ident("name") => match &arg.value {
+                    Expr::Lit(lit) => match &lit.lit {
+                        Lit::Str(s) => name = Some(s.value()),
+                        _ => return Err(syn::Error::new_spanned(lit, "name must be a string")),
+                    },
+                    value => return Err(syn::Error::new_spanned(value, "name must be a string")),
+                },
+                Meta::List(list) if list.path.is_ident("annotations") => {
+                    annotations.extend(
+                        list.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?,
+                    );
+                }
+                Meta::List(list) if list.path.is_ident("metadata") => {
+                    metadata.extend(
+                        list.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?,
+                    );
+                }
+                _ => return Err(syn::Error::new_spanned(arg, "unknown argument")),
+            }
+        }
+        Ok(Self {
+            context: context.unwrap_or_else(|| syn::parse_quote!(context)),
+            name,
+            annotations,
+            metadata,
+            err,
+        })
+    }
+}
+
+fn expand(args: Punctuated<Meta, Token![,]>, item: ItemFn) -> syn::Result<TokenStream2> {
+    let args = TracedArgs::parse(args)?;
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item;
+    let context = &args.context;
+    let name = args.name.unwrap_or_else(|| sig.ident.to_string());
+    let annotations = args.annotations.iter().map(|arg| {
+        let key = arg.to_string();
+        quote! {
+            __xray_namespace.annotation(#key, ::core::clone::Clone::clone(&#arg));
+        }
+    });
+    let metadata = args.metadata.iter().map(|arg| {
+        let key = arg.to_string();
+        quote! {
+            __xray_namespace.metadata(#key, &#arg);
+        }
+    });
+    let fake_return = fake_return(&sig.output);
+    let is_result = args.err || is_result(&sig.output);
+    let body = if sig.asyncness.is_some() {
+        let traced = if is_result {
+            quote!(traced_async_result)
+        } else {
+            quote!(traced_async)
+        };
+        quote! {
+            ::xray_lite::__private::#traced(
+                ::core::clone::Clone::clone(&#context),
+                __xray_namespace,
+                async move {
+                    #fake_return
+                    #block
+                },
+            )
+            .await
+        }
+    } else {
+        let traced = if is_result {
+            quote!(traced_result)
+        } else {
+            quote!(traced)
+        };
+        quote! {
+            ::xray_lite::__private::#traced(&#context, __xray_namespace, || {
+                #fake_return
+                #block
+            })
+        }
+    };
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            let __xray_namespace = {
+                let mut __xray_namespace = ::xray_lite::CustomNamespace::new(#name);
+                #(#annotations)*
+                #(#metadata)*
+                __xray_namespace
+            };
+            #body
+        }
+    })
+}
+
+// pins the return type of the closure or async block to the return type of
+// the function so that `?` and `return` in the body can be inferred.
+fn fake_return(output: &ReturnType) -> TokenStream2 {
+    let ty = match output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ty) if contains_impl_trait(ty) => return TokenStream2::new(),
+        ReturnType::Type(_, ty) => ty.to_token_stream(),
+    };
+    quote! {
+        #[allow(
+            unknown_lints,
+            unreachable_code,
+            clippy::diverging_sub_expression,
+            clippy::empty_loop,
+            clippy::let_unit_value,
+            clippy::needless_return,
+        )]
+        if false {
+            let __xray_fake_return: #ty = loop {};
+            return __xray_fake_return;
+        }
+    }
+}
+
+// whether the return type is `Result`, whose error is recorded.
+//
+// only the name is checked; aliases with other names need the `err` argument.
+fn is_result(output: &ReturnType) -> bool {
+    match output {
+        ReturnType::Default => false,
+        ReturnType::Type(_, ty) => match ty.as_ref() {
+            Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Result"),
+            _ => false,
+        },
+    }
+}
+
+fn contains_impl_trait(ty: &Type) -> bool {
+    struct Visitor(bool);
+    impl<'ast> syn::visit::Visit<'ast> for Visitor {
+        fn visit_type_impl_trait(&mut self, _: &'ast TypeImplTrait) {
+            self.0 = true;
+        }
+    }
+    let mut visitor = Visitor(false);
+    syn::visit::Visit::visit_type(&mut visitor, ty);
+    visitor.0
+}
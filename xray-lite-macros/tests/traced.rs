@@ -0,0 +1,135 @@
+use xray_lite::testing::{self, RecordingClient};
+use xray_lite::{traced, Context, InProgressPolicy, SubsegmentContext};
+
+fn context(client: &RecordingClient) -> SubsegmentContext<RecordingClient> {
+    testing::context(client).with_in_progress_policy(InProgressPolicy::Never)
+}
+
+#[traced(annotations(id), metadata(tags))]
+fn parse_id(context: &impl Context, id: &str, tags: &[&str]) -> std::result::Result<u32, String> {
+    let value = id.parse::<u32>().map_err(|e| e.to_string())?;
+    Ok(value)
+}
+
+type Outcome<T> = std::result::Result<T, String>;
+
+#[traced(err)]
+fn check(context: &impl Context, ok: bool) -> Outcome<()> {
+    if ok {
+        Ok(())
+    } else {
+        Err("not ok".to_string())
+    }
+}
+
+#[traced(name = "sum")]
+fn add(context: &impl Context, a: u32, b: u32) -> u32 {
+    if a == 0 {
+        return b;
+    }
+    a + b
+}
+
+#[traced]
+async fn fetch(
+    context: SubsegmentContext<RecordingClient>,
+    id: &str,
+) -> std::result::Result<u32, String> {
+    tokio::task::yield_now().await;
+    child(&context)?;
+    parse_id(&context, id, &[])
+}
+
+#[traced]
+fn child(context: &impl Context) -> std::result::Result<(), String> {
+    Ok(())
+}
+
+struct Service {
+    context: SubsegmentContext<RecordingClient>,
+}
+
+impl Service {
+    #[traced(context = self.context)]
+    async fn handle(&self) -> usize {
+        self.context
+            .enter_subsegment(xray_lite::CustomNamespace::new("inner"));
+        42
+    }
+}
+
+#[test]
+fn traced_should_record_annotations_and_metadata() {
+    let client = RecordingClient::default();
+    let context = context(&client).with_name_prefix("test.");
+    assert_eq!(parse_id(&context, "42", &["a", "b"]), Ok(42));
+    let documents = client.documents();
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0]["name"], "test.parse_id");
+    assert_eq!(documents[0]["annotations"]["id"], "42");
+    assert_eq!(documents[0]["metadata"]["default"]["tags"][1], "b");
+    assert!(documents[0].get("fault").is_none());
+}
+
+#[test]
+fn traced_should_record_error() {
+    let client = RecordingClient::default();
+    let context = context(&client);
+    assert!(parse_id(&context, "x", &[]).is_err());
+    let documents = client.documents();
+    assert_eq!(documents[0]["fault"], true);
+    assert_eq!(
+        documents[0]["cause"]["exceptions"][0]["message"],
+        "invalid digit found in string",
+    );
+}
+
+#[test]
+fn traced_should_record_error_of_result_alias_with_err() {
+    let client = RecordingClient::default();
+    let context = context(&client);
+    assert!(check(&context, true).is_ok());
+    assert!(check(&context, false).is_err());
+    let documents = client.documents();
+    assert!(documents[0].get("fault").is_none());
+    assert_eq!(documents[1]["fault"], true);
+    assert_eq!(documents[1]["cause"]["exceptions"][0]["message"], "not ok");
+}
+
+#[test]
+fn traced_should_support_any_return_type() {
+    let client = RecordingClient::default();
+    let context = context(&client);
+    assert_eq!(add(&context, 0, 2), 2);
+    assert_eq!(add(&context, 1, 2), 3);
+    let documents = client.documents();
+    assert_eq!(documents.len(), 2);
+    assert_eq!(documents[0]["name"], "sum");
+}
+
+#[tokio::test]
+async fn traced_should_parent_nested_async_calls() {
+    let client = RecordingClient::default();
+    let context = context(&client);
+    assert_eq!(fetch(context.clone(), "7").await, Ok(7));
+    let documents = client.documents();
+    assert_eq!(documents.len(), 3);
+    assert_eq!(documents[0]["name"], "child");
+    assert_eq!(documents[1]["name"], "parse_id");
+    assert_eq!(documents[2]["name"], "fetch");
+    assert_eq!(documents[0]["parent_id"], documents[2]["id"]);
+    assert_eq!(documents[1]["parent_id"], documents[2]["id"]);
+}
+
+#[tokio::test]
+async fn traced_should_accept_context_expression() {
+    let client = RecordingClient::default();
+    let service = Service {
+        context: context(&client),
+    };
+    assert_eq!(service.handle().await, 42);
+    let documents = client.documents();
+    assert_eq!(documents.len(), 2);
+    assert_eq!(documents[1]["name"], "handle");
+    assert_eq!(documents[0]["parent_id"], documents[1]["id"]);
+}
@@ -0,0 +1,430 @@
+//! Asynchronous clients for Tokio.
+
+use std::future::Future;
+use std::net::SocketAddr;
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt as _;
+use tokio::net::{lookup_host, TcpStream, UdpSocket};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::time::timeout;
+
+use crate::client::{closed_or_io, Client, DaemonAddress, DaemonClient};
+use crate::error::{Error, Result};
+use crate::hook::report_error;
+use crate::stats::ClientStats;
+
+/// Asynchronous X-Ray client interface.
+///
+/// An asynchronous client cannot be used by a
+/// [`SubsegmentSession`][crate::SubsegmentSession], which sends documents
+/// while it is dropped. Wrap it in an [`AsyncClientBridge`] instead.
+pub trait AsyncClient: Clone + std::fmt::Debug + Send + Sync + 'static {
+    /// Sends a segment to the xray daemon this client is connected to.
+    fn send<S>(&self, data: &S) -> impl Future<Output = Result<()>> + Send
+    where
+        S: Serialize;
+
+    /// Returns the statistics of this client if it keeps them.
+    fn stats(&self) -> Option<&ClientStats> {
+        None
+    }
+}
+
+/// Asynchronous X-Ray daemon client.
+///
+/// This is the asynchronous counterpart of [`DaemonClient`] over Tokio UDP
+/// and TCP sockets. Oversized documents are split in the same way.
+#[derive(Clone, Debug)]
+pub struct AsyncDaemonClient {
+    transport: std::sync::Arc<Transport>,
+    max_packet_size: usize,
+    stats: ClientStats,
+}
+
+#[derive(Debug)]
+enum Transport {
+    Udp(UdpSocket),
+    Tcp {
+        addr: SocketAddr,
+        // `None` after a failure until reconnected
+        stream: Mutex<Option<TcpStream>>,
+    },
+}
+
+impl AsyncDaemonClient {
+    /// Returns a new X-Ray client connected to the UDP endpoint at `addr`.
+    pub async fn new(addr: SocketAddr) -> Result<Self> {
+        let local: SocketAddr = if addr.is_ipv6() {
+            ([0u16; 8], 0).into()
+        } else {
+            ([0, 0, 0, 0], 0).into()
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+        Ok(Self::with_transport(Transport::Udp(socket)))
+    }
+
+    /// Returns a new X-Ray client connected to the TCP endpoint at `addr`.
+    ///
+    /// Documents are delimited by newlines. The client reconnects on the next
+    /// send if the connection is lost.
+    pub async fn new_tcp(addr: SocketAddr) -> Result<Self> {
+        let stream = connect_tcp(&addr).await?;
+        Ok(Self::with_transport(Transport::Tcp {
+            addr,
+            stream: Mutex::new(Some(stream)),
+        }))
+    }
+
+    /// Returns a new X-Ray client connected to a given daemon address.
+    ///
+    /// The same forms as [`DaemonClient::from_address`] are accepted except
+    /// for Unix datagram sockets.
+    pub async fn from_address(address: &str) -> Result<Self> {
+        match address.parse::<DaemonAddress>()? {
+            DaemonAddress::Udp(address) => Self::new(resolve(&address).await?).await,
+            DaemonAddress::Tcp(address) => Self::new_tcp(resolve(&address).await?).await,
+            DaemonAddress::Unix(_) => Err(Error::InvalidDaemonAddress {
+                address: address.into(),
+                source: Some(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "Unix domain sockets are not supported by the asynchronous client",
+                )),
+            }),
+        }
+    }
+
+    /// Creates a new X-Ray client from the Lambda environment variable.
+    ///
+    /// See [`DaemonClient::from_lambda_env`] for details.
+    pub async fn from_lambda_env() -> Result<Self> {
+        let address = std::env::var("AWS_XRAY_DAEMON_ADDRESS")
+            .map_err(|_| Error::MissingEnvVar("AWS_XRAY_DAEMON_ADDRESS"))?;
+        Self::from_address(&address).await
+    }
+
+    fn with_transport(transport: Transport) -> Self {
+        Self {
+            transport: std::sync::Arc::new(transport),
+            max_packet_size: DaemonClient::DEFAULT_MAX_PACKET_SIZE,
+            stats: ClientStats::new(),
+        }
+    }
+
+    /// Updates the maximum size of a packet including the header.
+    pub fn with_max_packet_size(self, max_packet_size: usize) -> Self {
+        Self {
+            max_packet_size,
+            ..self
+        }
+    }
+}
+
+impl AsyncClient for AsyncDaemonClient {
+    fn send<S>(&self, data: &S) -> impl Future<Output = Result<()>> + Send
+    where
+        S: Serialize,
+    {
+        // serializes before awaiting so that the future does not borrow `data`
+        let packets = DaemonClient::split_packets(data, self.max_packet_size);
+        async move {
+            let packets = packets.inspect_err(|e| self.stats.record_packet_failure(e))?;
+            for packet in packets {
+                self.transport.send(&packet).await.inspect_err(|_| {
+                    self.stats.record_socket_error();
+                })?;
+                self.stats.record_sent(packet.len());
+            }
+            Ok(())
+        }
+    }
+
+    fn stats(&self) -> Option<&ClientStats> {
+        Some(&self.stats)
+    }
+}
+
+impl Transport {
+    async fn send(&self, packet: &[u8]) -> Result<()> {
+        match self {
+            Self::Udp(socket) => {
+                socket.send(packet).await?;
+            }
+            Self::Tcp { addr, stream } => {
+                let mut stream = stream.lock().await;
+                if stream.is_none() {
+                    *stream = Some(connect_tcp(addr).await?);
+                }
+                if let Some(connected) = stream.as_mut() {
+                    // documents on a stream are delimited by newlines
+                    let write = async {
+                        connected.write_all(packet).await?;
+                        connected.write_all(DaemonClient::DELIMITER).await
+                    };
+                    let result = timeout(DaemonClient::TCP_TIMEOUT, write)
+                        .await
+                        .unwrap_or_else(|e| Err(e.into()));
+                    if let Err(e) = result {
+                        *stream = None;
+                        return Err(closed_or_io(e));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn connect_tcp(addr: &SocketAddr) -> std::io::Result<TcpStream> {
+    let stream = timeout(DaemonClient::TCP_TIMEOUT, TcpStream::connect(addr)).await??;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+// prefers IPv4 as the daemon listens on IPv4 by default.
+async fn resolve(address: &str) -> Result<SocketAddr> {
+    let addrs: Vec<SocketAddr> = lookup_host(address)
+        .await
+        .map_err(|e| Error::InvalidDaemonAddress {
+            address: address.into(),
+            source: Some(e),
+        })?
+        .collect();
+    addrs
+        .iter()
+        .find(|addr| addr.is_ipv4())
+        .or(addrs.first())
+        .copied()
+        .ok_or_else(|| Error::InvalidDaemonAddress {
+            address: address.into(),
+            source: None,
+        })
+}
+
+/// Bridge from an [`AsyncClient`] to [`Client`].
+///
+/// [`Client::send`] only queues a document and never blocks; a task spawned
+/// on the Tokio runtime sends queued documents with the asynchronous client.
+/// Errors in the task are reported to the
+/// [error hook][crate::set_error_hook]. Call [`AsyncClientBridge::flush`]
+/// before a Lambda invocation ends so that no document is left in the queue
+/// while the execution environment is frozen.
+///
+/// ```
+/// use xray_lite::{AsyncClientBridge, AsyncDaemonClient, Context as _, CustomNamespace, SubsegmentContext};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let client = AsyncDaemonClient::new(([127, 0, 0, 1], 2000).into()).await.unwrap();
+/// let client = AsyncClientBridge::new(client);
+/// let context = SubsegmentContext::with_header(
+///     client.clone(),
+///     "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1"
+///         .parse()
+///         .unwrap(),
+/// );
+/// {
+///     let _session = context.enter_subsegment(CustomNamespace::new("work"));
+/// }
+/// client.flush().await.unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct AsyncClientBridge {
+    sender: mpsc::Sender<Command>,
+    capacity: usize,
+    stats: ClientStats,
+}
+
+#[derive(Debug)]
+enum Command {
+    Document(Value),
+    Flush(oneshot::Sender<()>),
+}
+
+impl AsyncClientBridge {
+    /// Default maximum number of documents waiting to be sent.
+    pub const DEFAULT_QUEUE_CAPACITY: usize = 10_000;
+
+    /// Creates a bridge with the default queue capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn new<A>(client: A) -> Self
+    where
+        A: AsyncClient,
+    {
+        Self::with_queue_capacity(client, Self::DEFAULT_QUEUE_CAPACITY)
+    }
+
+    /// Creates a bridge with a given queue capacity.
+    ///
+    /// Documents sent while the queue is full are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn with_queue_capacity<A>(client: A, capacity: usize) -> Self
+    where
+        A: AsyncClient,
+    {
+        let capacity = capacity.max(1);
+        let (sender, receiver) = mpsc::channel(capacity);
+        let stats = client.stats().cloned().unwrap_or_default();
+        tokio::spawn(run(client, receiver));
+        Self {
+            sender,
+            capacity,
+            stats,
+        }
+    }
+
+    /// Waits until the documents queued so far are sent or failed.
+    pub async fn flush(&self) -> Result<()> {
+        let (done, wait) = oneshot::channel();
+        self.sender
+            .send(Command::Flush(done))
+            .await
+            .map_err(|_| Error::TransportClosed { source: None })?;
+        wait.await
+            .map_err(|_| Error::TransportClosed { source: None })
+    }
+}
+
+impl Client for AsyncClientBridge {
+    /// Queues a document.
+    ///
+    /// Fails with [`Error::QueueFull`] if the queue is full, and with
+    /// [`Error::TransportClosed`] if the runtime has shut down.
+    fn send<S>(&self, data: &S) -> Result<()>
+    where
+        S: Serialize,
+    {
+        let document = serde_json::to_value(data).inspect_err(|_| {
+            self.stats.record_serialization_failure();
+        })?;
+        self.sender
+            .try_send(Command::Document(document))
+            .map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => Error::QueueFull {
+                    capacity: self.capacity,
+                },
+                mpsc::error::TrySendError::Closed(_) => Error::TransportClosed { source: None },
+            })
+            .inspect_err(|_| self.stats.record_queue_drops(1))
+    }
+
+    fn stats(&self) -> Option<&ClientStats> {
+        Some(&self.stats)
+    }
+}
+
+async fn run<A>(client: A, mut receiver: mpsc::Receiver<Command>)
+where
+    A: AsyncClient,
+{
+    while let Some(command) = receiver.recv().await {
+        match command {
+            Command::Document(document) => {
+                if let Err(e) = client.send(&document).await {
+                    report_error("failed to send document", &e);
+                }
+            }
+            Command::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::{Context as _, CustomNamespace, SubsegmentContext};
+
+    #[tokio::test]
+    async fn async_daemon_client_should_send_over_udp() {
+        let daemon = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = AsyncDaemonClient::from_address(&daemon.local_addr().unwrap().to_string())
+            .await
+            .unwrap();
+        client
+            .send(&serde_json::json!({"foo": "bar"}))
+            .await
+            .unwrap();
+        let mut buf = [0u8; 128];
+        let size = timeout(Duration::from_secs(1), daemon.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            &buf[..size],
+            &br#"{"format": "json", "version": 1}
+{"foo":"bar"}"#[..]
+        );
+        assert_eq!(client.stats().unwrap().snapshot().documents_sent, 1);
+    }
+
+    #[tokio::test]
+    async fn async_daemon_client_should_send_over_tcp() {
+        use tokio::io::AsyncBufReadExt as _;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = AsyncDaemonClient::from_address(&format!("tcp:127.0.0.1:{port}"))
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        client.send(&serde_json::json!({"foo": 1})).await.unwrap();
+        let mut lines = tokio::io::BufReader::new(stream).lines();
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            r#"{"format": "json", "version": 1}"#
+        );
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), r#"{"foo":1}"#);
+    }
+
+    #[tokio::test]
+    async fn async_client_bridge_should_send_documents_of_sessions() {
+        let daemon = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = AsyncDaemonClient::new(daemon.local_addr().unwrap())
+            .await
+            .unwrap();
+        let client = AsyncClientBridge::new(client);
+        let context = SubsegmentContext::with_header(
+            client.clone(),
+            "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1"
+                .parse()
+                .unwrap(),
+        );
+        drop(context.enter_subsegment(CustomNamespace::new("bridged")));
+        client.flush().await.unwrap();
+        assert_eq!(client.stats().unwrap().snapshot().documents_sent, 2);
+        let mut buf = [0u8; 1024];
+        for _ in 0..2 {
+            let size = daemon.recv(&mut buf).await.unwrap();
+            assert!(String::from_utf8_lossy(&buf[..size]).contains(r#""name":"bridged""#));
+        }
+    }
+
+    #[tokio::test]
+    async fn async_client_bridge_should_fail_when_queue_is_full() {
+        let client = AsyncDaemonClient::new(([127, 0, 0, 1], 2000).into())
+            .await
+            .unwrap();
+        // the task does not run until this test yields on the current thread
+        let client = AsyncClientBridge::with_queue_capacity(client, 1);
+        client.send(&serde_json::json!({"n": 1})).unwrap();
+        assert!(matches!(
+            client.send(&serde_json::json!({"n": 2})),
+            Err(Error::QueueFull { capacity: 1 })
+        ));
+        assert_eq!(client.stats().unwrap().snapshot().queue_drops, 1);
+    }
+}
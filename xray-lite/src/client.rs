@@ -1,12 +1,20 @@
 //! X-Ray daemon client.
 
 use std::env;
-use std::net::{SocketAddr, UdpSocket};
-use std::sync::Arc;
+use std::io::Write as _;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
 use serde::Serialize;
 
 use crate::error::{Error, Result};
+use crate::split::split_document;
+use crate::stats::ClientStats;
 
 /// X-Ray client interface.
 pub trait Client: Clone + std::fmt::Debug + Send + Sync {
@@ -14,25 +22,206 @@ pub trait Client: Clone + std::fmt::Debug + Send + Sync {
     fn send<S>(&self, data: &S) -> Result<()>
     where
         S: Serialize;
+
+    /// Returns the statistics of this client if it keeps them.
+    fn stats(&self) -> Option<&ClientStats> {
+        None
+    }
 }
 
 /// X-Ray daemon client.
+///
+/// Documents are sent over UDP by default. TCP and Unix datagram sockets are
+/// also supported for collectors that accept them; e.g., the CloudWatch agent
+/// and the AWS Distro for OpenTelemetry collector.
+///
+/// A document that does not fit in a UDP packet is split: embedded
+/// subsegments are streamed as separate documents, and if that is not enough,
+/// the metadata is replaced with `{"xray_lite": {"truncated": true}}`.
 #[derive(Clone, Debug)]
 pub struct DaemonClient {
-    socket: Arc<UdpSocket>,
+    transport: Arc<Transport>,
+    max_packet_size: usize,
+    stats: ClientStats,
+}
+
+#[derive(Debug)]
+enum Transport {
+    Udp(UdpSocket),
+    Tcp {
+        addr: SocketAddr,
+        // `None` after a failure until reconnected
+        stream: Mutex<Option<TcpStream>>,
+    },
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+}
+
+impl Transport {
+    fn send(&self, packet: &[u8]) -> Result<()> {
+        match self {
+            Self::Udp(socket) => {
+                socket.send(packet)?;
+            }
+            Self::Tcp { addr, stream } => {
+                let mut stream = stream.lock().unwrap_or_else(PoisonError::into_inner);
+                if stream.is_none() {
+                    *stream = Some(connect_tcp(addr)?);
+                }
+                if let Some(connected) = stream.as_mut() {
+                    // documents on a stream are delimited by newlines
+                    let result = connected
+                        .write_all(packet)
+                        .and_then(|_| connected.write_all(DaemonClient::DELIMITER));
+                    if let Err(e) = result {
+                        *stream = None;
+                        return Err(closed_or_io(e));
+                    }
+                }
+            }
+            #[cfg(unix)]
+            Self::Unix(socket) => {
+                socket.send(packet)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// distinguishes a lost connection from other I/O errors.
+pub(crate) fn closed_or_io(e: std::io::Error) -> Error {
+    use std::io::ErrorKind;
+    match e.kind() {
+        ErrorKind::BrokenPipe
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::NotConnected => Error::TransportClosed { source: Some(e) },
+        _ => Error::IO(e),
+    }
+}
+
+fn connect_tcp(addr: &SocketAddr) -> std::io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(addr, DaemonClient::TCP_TIMEOUT)?;
+    stream.set_write_timeout(Some(DaemonClient::TCP_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+// prefers IPv4 as the daemon listens on IPv4 by default.
+pub(crate) fn resolve(address: &str) -> Result<SocketAddr> {
+    let addrs: Vec<SocketAddr> = address
+        .to_socket_addrs()
+        .map_err(|e| Error::InvalidDaemonAddress {
+            address: address.into(),
+            source: Some(e),
+        })?
+        .collect();
+    addrs
+        .iter()
+        .find(|addr| addr.is_ipv4())
+        .or(addrs.first())
+        .copied()
+        .ok_or_else(|| Error::InvalidDaemonAddress {
+            address: address.into(),
+            source: None,
+        })
 }
 
 impl DaemonClient {
-    const HEADER: &'static [u8] = br#"{"format": "json", "version": 1}"#;
-    const DELIMITER: &'static [u8] = &[b'\n'];
+    pub(crate) const HEADER: &'static [u8] = br#"{"format": "json", "version": 1}"#;
+    // spelled as in the daemon protocol
+    #[allow(clippy::byte_char_slices)]
+    pub(crate) const DELIMITER: &'static [u8] = &[b'\n'];
+
+    /// Default maximum size of a packet including the header.
+    ///
+    /// This is the maximum UDP payload over IPv4, which is below the 64KB
+    /// buffer of the X-Ray daemon.
+    pub const DEFAULT_MAX_PACKET_SIZE: usize = 65_507;
+
+    /// Timeout to connect and write to a TCP endpoint.
+    pub(crate) const TCP_TIMEOUT: Duration = Duration::from_secs(1);
 
     /// Return a new X-Ray client connected
     /// to the provided `addr`
     pub fn new(addr: SocketAddr) -> Result<Self> {
-        let socket = Arc::new(UdpSocket::bind(&[([0, 0, 0, 0], 0).into()][..])?);
+        let local: SocketAddr = if addr.is_ipv6() {
+            ([0u16; 8], 0).into()
+        } else {
+            ([0, 0, 0, 0], 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
         socket.set_nonblocking(true)?;
         socket.connect(addr)?;
-        Ok(DaemonClient { socket })
+        Ok(Self::with_transport(Transport::Udp(socket)))
+    }
+
+    /// Returns a new X-Ray client connected to the TCP endpoint at `addr`.
+    ///
+    /// Documents are delimited by newlines. The client reconnects on the next
+    /// send if the connection is lost.
+    pub fn new_tcp(addr: SocketAddr) -> Result<Self> {
+        let stream = connect_tcp(&addr)?;
+        Ok(Self::with_transport(Transport::Tcp {
+            addr,
+            stream: Mutex::new(Some(stream)),
+        }))
+    }
+
+    /// Returns a new X-Ray client connected to the Unix datagram socket at
+    /// `path`.
+    #[cfg(unix)]
+    pub fn new_unix(path: impl AsRef<Path>) -> Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.set_nonblocking(true)?;
+        socket.connect(path)?;
+        Ok(Self::with_transport(Transport::Unix(socket)))
+    }
+
+    /// Returns a new X-Ray client connected to a given daemon address.
+    ///
+    /// The following forms are accepted, where `host` is a hostname or an IP
+    /// address:
+    /// - `host:port`: UDP
+    /// - `udp:host:port`: UDP
+    /// - `tcp:host:port udp:host:port`: UDP; the TCP address of the daemon is
+    ///   for sampling rules and ignored. The order does not matter.
+    /// - `tcp:host:port`: TCP
+    /// - `unix:path`: Unix datagram socket (Unix only)
+    ///
+    /// Fails with [`Error::InvalidDaemonAddress`] if the address is malformed,
+    /// cannot be resolved, or is not supported on this platform.
+    pub fn from_address(address: &str) -> Result<Self> {
+        match address.parse::<DaemonAddress>()? {
+            DaemonAddress::Udp(address) => Self::new(resolve(&address)?),
+            DaemonAddress::Tcp(address) => Self::new_tcp(resolve(&address)?),
+            #[cfg(unix)]
+            DaemonAddress::Unix(path) => Self::new_unix(path),
+            #[cfg(not(unix))]
+            DaemonAddress::Unix(_) => Err(Error::InvalidDaemonAddress {
+                address: address.into(),
+                source: Some(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "Unix domain sockets are not supported on this platform",
+                )),
+            }),
+        }
+    }
+
+    fn with_transport(transport: Transport) -> Self {
+        Self {
+            transport: Arc::new(transport),
+            max_packet_size: Self::DEFAULT_MAX_PACKET_SIZE,
+            stats: ClientStats::new(),
+        }
+    }
+
+    /// Updates the maximum size of a packet including the header.
+    pub fn with_max_packet_size(self, max_packet_size: usize) -> Self {
+        Self {
+            max_packet_size,
+            ..self
+        }
     }
 
     /// Creates a new X-Ray client from the Lambda environment variable.
@@ -41,22 +230,40 @@ impl DaemonClient {
     /// - `AWS_XRAY_DAEMON_ADDRESS`: X-Ray daemon address
     ///
     /// Please refer to the [AWS documentation](https://docs.aws.amazon.com/lambda/latest/dg/configuration-envvars.html#configuration-envvars-runtime)
-    /// for more details.
+    /// for more details. See [`DaemonClient::from_address`] for the accepted
+    /// forms of the address.
     pub fn from_lambda_env() -> Result<Self> {
-        let addr: SocketAddr = env::var("AWS_XRAY_DAEMON_ADDRESS")
-            .map_err(|_| Error::MissingEnvVar("AWS_XRAY_DAEMON_ADDRESS"))?
-            .parse::<SocketAddr>()
-            .map_err(|e| Error::BadConfig(format!("invalid X-Ray daemon address: {e}")))?;
-        DaemonClient::new(addr)
+        let address = env::var("AWS_XRAY_DAEMON_ADDRESS")
+            .map_err(|_| Error::MissingEnvVar("AWS_XRAY_DAEMON_ADDRESS"))?;
+        DaemonClient::from_address(&address)
     }
 
     #[inline]
-    fn packet<S>(data: S) -> Result<Vec<u8>>
+    fn packet(bytes: &[u8]) -> Vec<u8> {
+        [Self::HEADER, Self::DELIMITER, bytes].concat()
+    }
+
+    fn packets<S>(&self, data: &S) -> Result<Vec<Vec<u8>>>
+    where
+        S: Serialize,
+    {
+        Self::split_packets(data, self.max_packet_size)
+    }
+
+    // serializes a document into packets of at most `max_packet_size` bytes.
+    pub(crate) fn split_packets<S>(data: &S, max_packet_size: usize) -> Result<Vec<Vec<u8>>>
     where
         S: Serialize,
     {
-        let bytes = serde_json::to_vec(&data)?;
-        Ok([Self::HEADER, Self::DELIMITER, &bytes].concat())
+        let bytes = serde_json::to_vec(data)?;
+        let overhead = Self::HEADER.len() + Self::DELIMITER.len();
+        let documents = if bytes.len() + overhead <= max_packet_size {
+            vec![bytes]
+        } else {
+            let document = serde_json::from_slice(&bytes)?;
+            split_document(document, max_packet_size.saturating_sub(overhead))?
+        };
+        Ok(documents.iter().map(|bytes| Self::packet(bytes)).collect())
     }
 }
 
@@ -65,9 +272,63 @@ impl Client for DaemonClient {
     where
         S: Serialize,
     {
-        self.socket.send(&Self::packet(data)?)?;
+        let packets = self
+            .packets(data)
+            .inspect_err(|e| self.stats.record_packet_failure(e))?;
+        for packet in packets {
+            self.transport.send(&packet).inspect_err(|_| {
+                self.stats.record_socket_error();
+            })?;
+            self.stats.record_sent(packet.len());
+        }
         Ok(())
     }
+
+    fn stats(&self) -> Option<&ClientStats> {
+        Some(&self.stats)
+    }
+}
+
+/// Parsed X-Ray daemon address.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum DaemonAddress {
+    Udp(String),
+    Tcp(String),
+    Unix(String),
+}
+
+impl std::str::FromStr for DaemonAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bad = || Error::InvalidDaemonAddress {
+            address: s.into(),
+            source: None,
+        };
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        match parts[..] {
+            [part] => {
+                if let Some(path) = part.strip_prefix("unix:") {
+                    Ok(Self::Unix(path.into()))
+                } else if let Some(address) = part.strip_prefix("tcp:") {
+                    Ok(Self::Tcp(address.into()))
+                } else {
+                    Ok(Self::Udp(part.strip_prefix("udp:").unwrap_or(part).into()))
+                }
+            }
+            [first, second] => {
+                let udp = [first, second]
+                    .into_iter()
+                    .find_map(|part| part.strip_prefix("udp:"))
+                    .ok_or_else(bad)?;
+                if ![first, second].iter().any(|part| part.starts_with("tcp:")) {
+                    return Err(bad());
+                }
+                Ok(Self::Udp(udp.into()))
+            }
+            _ => Err(bad()),
+        }
+    }
 }
 
 /// Infallible client.
@@ -102,6 +363,13 @@ where
             Self::Noop => Ok(()),
         }
     }
+
+    fn stats(&self) -> Option<&ClientStats> {
+        match self {
+            Self::Op(client) => client.stats(),
+            Self::Noop => None,
+        }
+    }
 }
 
 /// Conversion into an [`InfallibleClient`].
@@ -145,19 +413,165 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    use std::io::{BufRead as _, BufReader};
+    use std::net::TcpListener;
+
+    fn client() -> DaemonClient {
+        DaemonClient::new(([127, 0, 0, 1], 2000).into()).unwrap()
+    }
+
     #[test]
+    #[allow(clippy::byte_char_slices)]
     fn client_prefixes_packets_with_header() {
         assert_eq!(
-            DaemonClient::packet(serde_json::json!({
-                "foo": "bar"
-            }))
-            .unwrap(),
-            [
+            client()
+                .packets(&serde_json::json!({
+                    "foo": "bar"
+                }))
+                .unwrap(),
+            vec![[
                 br#"{"format": "json", "version": 1}"# as &[u8],
                 &[b'\n'],
                 br#"{"foo":"bar"}"#,
             ]
-            .concat()
+            .concat()]
         )
     }
+
+    #[test]
+    fn client_splits_oversized_documents() {
+        let client = client().with_max_packet_size(400);
+        let packets = client
+            .packets(&serde_json::json!({
+                "trace_id": "1-5759e988-bd862e3fe1be46a994272793",
+                "id": "53995c3f42cd8ad8",
+                "subsegments": [
+                    {"id": "0000000000000001", "name": "x".repeat(150)},
+                    {"id": "0000000000000002", "name": "y".repeat(150)},
+                ],
+            }))
+            .unwrap();
+        assert_eq!(packets.len(), 2);
+        for packet in packets {
+            assert!(packet.len() <= 400);
+            assert!(packet.starts_with(DaemonClient::HEADER));
+        }
+    }
+
+    #[test]
+    fn daemon_address_should_parse_all_forms() {
+        assert_eq!(
+            "127.0.0.1:2000".parse::<DaemonAddress>().unwrap(),
+            DaemonAddress::Udp("127.0.0.1:2000".into())
+        );
+        assert_eq!(
+            "udp:xray.local:2000".parse::<DaemonAddress>().unwrap(),
+            DaemonAddress::Udp("xray.local:2000".into())
+        );
+        assert_eq!(
+            "tcp:127.0.0.1:2000 udp:127.0.0.2:2001"
+                .parse::<DaemonAddress>()
+                .unwrap(),
+            DaemonAddress::Udp("127.0.0.2:2001".into())
+        );
+        assert_eq!(
+            "udp:127.0.0.2:2001 tcp:127.0.0.1:2000"
+                .parse::<DaemonAddress>()
+                .unwrap(),
+            DaemonAddress::Udp("127.0.0.2:2001".into())
+        );
+        assert_eq!(
+            "tcp:cwagent:2000".parse::<DaemonAddress>().unwrap(),
+            DaemonAddress::Tcp("cwagent:2000".into())
+        );
+        assert_eq!(
+            "unix:/tmp/xray.sock".parse::<DaemonAddress>().unwrap(),
+            DaemonAddress::Unix("/tmp/xray.sock".into())
+        );
+        assert!("tcp:127.0.0.1:2000 tcp:127.0.0.1:2001"
+            .parse::<DaemonAddress>()
+            .is_err());
+        assert!("".parse::<DaemonAddress>().is_err());
+    }
+
+    #[test]
+    fn client_should_resolve_hostnames() {
+        let daemon = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = daemon.local_addr().unwrap().port();
+        let client = DaemonClient::from_address(&format!("localhost:{port}")).unwrap();
+        client.send(&serde_json::json!({"foo": "bar"})).unwrap();
+        let mut buf = [0u8; 128];
+        daemon
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let size = daemon.recv(&mut buf).unwrap();
+        assert!(buf[..size].ends_with(br#"{"foo":"bar"}"#));
+    }
+
+    #[test]
+    fn client_should_record_stats() {
+        let daemon = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = DaemonClient::new(daemon.local_addr().unwrap())
+            .unwrap()
+            .with_max_packet_size(64);
+        client
+            .clone()
+            .send(&serde_json::json!({"foo": "bar"}))
+            .unwrap();
+        assert!(client
+            .send(&serde_json::json!({"id": "53995c3f42cd8ad8", "name": "x".repeat(64)}))
+            .is_err());
+        assert_eq!(
+            client.stats().unwrap().snapshot(),
+            crate::StatsSnapshot {
+                documents_sent: 1,
+                bytes_sent: 46,
+                queue_drops: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn client_should_send_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = DaemonClient::from_address(&format!("tcp:127.0.0.1:{port}")).unwrap();
+        client.send(&serde_json::json!({"foo": 1})).unwrap();
+        client.send(&serde_json::json!({"foo": 2})).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let lines: Vec<String> = BufReader::new(stream)
+            .lines()
+            .take(4)
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"format": "json", "version": 1}"#,
+                r#"{"foo":1}"#,
+                r#"{"format": "json", "version": 1}"#,
+                r#"{"foo":2}"#,
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn client_should_send_over_unix_datagram_socket() {
+        let path = std::env::temp_dir().join(format!("xray-lite-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let daemon = UnixDatagram::bind(&path).unwrap();
+        let client = DaemonClient::from_address(&format!("unix:{}", path.display())).unwrap();
+        client.send(&serde_json::json!({"foo": "bar"})).unwrap();
+        let mut buf = [0u8; 128];
+        let size = daemon.recv(&mut buf).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            &buf[..size],
+            &br#"{"format": "json", "version": 1}
+{"foo":"bar"}"#[..]
+        );
+    }
 }
@@ -0,0 +1,276 @@
+//! Client combinators.
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::client::Client;
+use crate::error::Result;
+use crate::stats::ClientStats;
+
+/// Client that sends documents to two clients.
+///
+/// Both clients receive every document even if the first one fails, and the
+/// first error is returned. Nest `Tee`s to send documents to more clients.
+///
+/// ```
+/// use xray_lite::{DaemonClient, Tee, WriterClient};
+///
+/// let client = Tee(
+///     DaemonClient::new(([127, 0, 0, 1], 2000).into()).unwrap(),
+///     WriterClient::stdout(),
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct Tee<A, B>(pub A, pub B);
+
+impl<A, B> Client for Tee<A, B>
+where
+    A: Client,
+    B: Client,
+{
+    fn send<S>(&self, data: &S) -> Result<()>
+    where
+        S: Serialize,
+    {
+        let first = self.0.send(data);
+        let second = self.1.send(data);
+        first.and(second)
+    }
+
+    /// Returns the statistics of the first client.
+    fn stats(&self) -> Option<&ClientStats> {
+        self.0.stats()
+    }
+}
+
+/// Client that drops documents not satisfying a predicate.
+///
+/// The predicate receives each document as a JSON value; e.g., to drop
+/// subsegments by name or namespace:
+///
+/// ```
+/// use xray_lite::{DaemonClient, Filter};
+///
+/// let client = Filter::new(
+///     DaemonClient::new(([127, 0, 0, 1], 2000).into()).unwrap(),
+///     |document| {
+///         document["name"] != "healthcheck" && document["namespace"] != "remote"
+///     },
+/// );
+/// ```
+pub struct Filter<C, F> {
+    client: C,
+    predicate: Arc<F>,
+}
+
+impl<C, F> Filter<C, F>
+where
+    C: Client,
+    F: Fn(&Value) -> bool + Send + Sync,
+{
+    /// Creates a client that sends documents satisfying `predicate` to a
+    /// given client.
+    pub fn new(client: C, predicate: F) -> Self {
+        Self {
+            client,
+            predicate: Arc::new(predicate),
+        }
+    }
+}
+
+impl<C, F> Clone for Filter<C, F>
+where
+    C: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            predicate: self.predicate.clone(),
+        }
+    }
+}
+
+impl<C, F> fmt::Debug for Filter<C, F>
+where
+    C: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Filter")
+            .field("client", &self.client)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C, F> Client for Filter<C, F>
+where
+    C: Client,
+    F: Fn(&Value) -> bool + Send + Sync,
+{
+    fn send<S>(&self, data: &S) -> Result<()>
+    where
+        S: Serialize,
+    {
+        let document = serde_json::to_value(data)?;
+        if (self.predicate)(&document) {
+            self.client.send(&document)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn stats(&self) -> Option<&ClientStats> {
+        self.client.stats()
+    }
+}
+
+/// Client that transforms documents before sending them.
+///
+/// The function receives each document as a JSON value and returns the
+/// document to send; e.g., to redact or enrich documents:
+///
+/// ```
+/// use xray_lite::{DaemonClient, Map};
+///
+/// let client = Map::new(
+///     DaemonClient::new(([127, 0, 0, 1], 2000).into()).unwrap(),
+///     |mut document| {
+///         if let Some(user) = document.get_mut("user") {
+///             *user = "REDACTED".into();
+///         }
+///         document
+///     },
+/// );
+/// ```
+pub struct Map<C, F> {
+    client: C,
+    f: Arc<F>,
+}
+
+impl<C, F> Map<C, F>
+where
+    C: Client,
+    F: Fn(Value) -> Value + Send + Sync,
+{
+    /// Creates a client that sends documents transformed with `f` to a given
+    /// client.
+    pub fn new(client: C, f: F) -> Self {
+        Self {
+            client,
+            f: Arc::new(f),
+        }
+    }
+}
+
+impl<C, F> Clone for Map<C, F>
+where
+    C: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            f: self.f.clone(),
+        }
+    }
+}
+
+impl<C, F> fmt::Debug for Map<C, F>
+where
+    C: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Map")
+            .field("client", &self.client)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C, F> Client for Map<C, F>
+where
+    C: Client,
+    F: Fn(Value) -> Value + Send + Sync,
+{
+    fn send<S>(&self, data: &S) -> Result<()>
+    where
+        S: Serialize,
+    {
+        let document = serde_json::to_value(data)?;
+        self.client.send(&(self.f)(document))
+    }
+
+    fn stats(&self) -> Option<&ClientStats> {
+        self.client.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    use crate::testing::{FailingClient, RecordingClient};
+
+    #[test]
+    fn tee_should_send_to_both_clients_even_if_first_fails() {
+        let a = RecordingClient::default();
+        let b = RecordingClient::default();
+        Tee(a.clone(), b.clone()).send(&json!({"id": 1})).unwrap();
+        assert_eq!(a.documents(), vec![json!({"id": 1})]);
+        assert_eq!(b.documents(), vec![json!({"id": 1})]);
+
+        let b = RecordingClient::default();
+        assert!(Tee(FailingClient, b.clone())
+            .send(&json!({"id": 2}))
+            .is_err());
+        assert_eq!(b.documents(), vec![json!({"id": 2})]);
+    }
+
+    #[test]
+    fn filter_should_drop_documents_not_satisfying_predicate() {
+        let recorder = RecordingClient::default();
+        let client = Filter::new(recorder.clone(), |document| document["name"] != "noisy");
+        client.send(&json!({"name": "noisy"})).unwrap();
+        client.send(&json!({"name": "important"})).unwrap();
+        assert_eq!(recorder.documents(), vec![json!({"name": "important"})]);
+    }
+
+    #[test]
+    fn map_should_transform_documents() {
+        let recorder = RecordingClient::default();
+        let client = Map::new(recorder.clone(), |mut document| {
+            document["annotations"] = json!({"stage": "prod"});
+            document
+        });
+        client.clone().send(&json!({"name": "work"})).unwrap();
+        assert_eq!(
+            recorder.documents(),
+            vec![json!({"name": "work", "annotations": {"stage": "prod"}})]
+        );
+    }
+
+    #[test]
+    fn combinators_should_compose() {
+        let daemon = RecordingClient::default();
+        let file = RecordingClient::default();
+        let client = Tee(
+            daemon.clone(),
+            Filter::new(
+                Map::new(file.clone(), |mut document| {
+                    document["user"] = "REDACTED".into();
+                    document
+                }),
+                |document| document["error"] == true,
+            ),
+        );
+        client.send(&json!({"user": "alice"})).unwrap();
+        client.send(&json!({"user": "bob", "error": true})).unwrap();
+        assert_eq!(daemon.documents().len(), 2);
+        assert_eq!(
+            file.documents(),
+            vec![json!({"user": "REDACTED", "error": true})]
+        );
+    }
+}
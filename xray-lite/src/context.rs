@@ -1,11 +1,14 @@
 //! Tracing context.
 
 use crate::client::Client;
+use crate::embedded::Embedding;
 use crate::error::Result;
 use crate::header::Header;
+use crate::instrument;
 use crate::lambda;
 use crate::namespace::Namespace;
-use crate::session::SubsegmentSession;
+use crate::precursor::Precursors;
+use crate::session::{InProgress, InProgressPolicy, SubsegmentSession};
 
 /// Context.
 pub trait Context {
@@ -15,18 +18,123 @@ pub trait Context {
     /// Enters in a new subsegment.
     ///
     /// [`SubsegmentSession`] records the end of the subsegment when it is
-    /// dropped.
+    /// dropped, or when it is ended explicitly with
+    /// [`SubsegmentSession::end`] or [`SubsegmentSession::end_with`].
     fn enter_subsegment<N>(&self, namespace: N) -> SubsegmentSession<Self::Client, N>
     where
         N: Namespace + Send + Sync;
+
+    /// Returns the trace header to propagate from this context.
+    ///
+    /// Its parent is the parent of subsegments entered in this context.
+    /// `None` if the context is non-operational, or does not know the header.
+    fn header(&self) -> Option<Header> {
+        None
+    }
+
+    /// Runs a closure in a new subsegment.
+    ///
+    /// Subsegments entered in any context of the same trace while the closure
+    /// runs become children of the subsegment. The subsegment ends when the
+    /// closure returns. Use [`Context::in_subsegment_result`] to record an
+    /// error returned from the closure.
+    ///
+    /// ```
+    /// use xray_lite::{Context as _, CustomNamespace, DaemonClient, SubsegmentContext};
+    ///
+    /// # std::env::set_var("AWS_XRAY_DAEMON_ADDRESS", "127.0.0.1:2000");
+    /// let client = DaemonClient::from_lambda_env().unwrap();
+    /// # std::env::set_var("_X_AMZN_TRACE_ID", "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1");
+    /// let context = SubsegmentContext::from_lambda_env(client).unwrap();
+    /// let answer = context.in_subsegment(CustomNamespace::new("answer"), || {
+    ///     // a child of "answer"
+    ///     let _child = context.enter_subsegment(CustomNamespace::new("think"));
+    ///     42
+    /// });
+    /// assert_eq!(answer, 42);
+    /// ```
+    fn in_subsegment<N, F, R>(&self, namespace: N, f: F) -> R
+    where
+        N: Namespace + Send + Sync,
+        F: FnOnce() -> R,
+    {
+        instrument::run_in_subsegment(self, namespace, f, |_| &())
+    }
+
+    /// Runs a closure returning a `Result` in a new subsegment.
+    ///
+    /// The same as [`Context::in_subsegment`], except that the subsegment
+    /// ends with the result of the closure; i.e., an `Err` marks the
+    /// subsegment as a fault and is recorded in the cause. See
+    /// [`SubsegmentSession::end_with`].
+    ///
+    /// ```
+    /// use xray_lite::{Context as _, CustomNamespace, DaemonClient, SubsegmentContext};
+    ///
+    /// # std::env::set_var("AWS_XRAY_DAEMON_ADDRESS", "127.0.0.1:2000");
+    /// let client = DaemonClient::from_lambda_env().unwrap();
+    /// # std::env::set_var("_X_AMZN_TRACE_ID", "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1");
+    /// let context = SubsegmentContext::from_lambda_env(client).unwrap();
+    /// let answer = context.in_subsegment_result(CustomNamespace::new("parse"), || {
+    ///     // a child of "parse"
+    ///     let _child = context.enter_subsegment(CustomNamespace::new("trim"));
+    ///     " 42 ".trim().parse::<u32>()
+    /// });
+    /// assert_eq!(answer, Ok(42));
+    /// ```
+    fn in_subsegment_result<N, F, T, E>(&self, namespace: N, f: F) -> std::result::Result<T, E>
+    where
+        N: Namespace + Send + Sync,
+        F: FnOnce() -> std::result::Result<T, E>,
+        E: std::fmt::Display,
+    {
+        instrument::run_in_subsegment(self, namespace, f, |output| output)
+    }
+}
+
+impl<T> Context for &T
+where
+    T: Context + ?Sized,
+{
+    type Client = T::Client;
+
+    fn enter_subsegment<N>(&self, namespace: N) -> SubsegmentSession<Self::Client, N>
+    where
+        N: Namespace + Send + Sync,
+    {
+        (**self).enter_subsegment(namespace)
+    }
+
+    fn header(&self) -> Option<Header> {
+        (**self).header()
+    }
 }
 
 /// Context as a subsegment of an existing segment.
 #[derive(Clone, Debug)]
 pub struct SubsegmentContext<C> {
     client: C,
-    header: Header,
     name_prefix: String,
+    in_progress: InProgress,
+    parent: Parent,
+}
+
+/// Where subsegments are entered.
+#[derive(Clone, Debug)]
+pub(crate) struct Parent {
+    /// Header with the parent of the subsegments.
+    pub(crate) header: Header,
+    /// How the subsegments are recorded.
+    pub(crate) embedding: Embedding,
+    /// Number of ancestors of the subsegments entered in this process.
+    pub(crate) depth: usize,
+    /// Tracker of the precursors of the subsegments if enabled.
+    pub(crate) precursors: Option<Precursors>,
+}
+
+impl<C> SubsegmentContext<C> {
+    /// Default maximum number of subsegments buffered in a subsegment tree.
+    pub const DEFAULT_STREAMING_THRESHOLD: usize = 100;
 }
 
 impl<C> SubsegmentContext<C> {
@@ -45,8 +153,28 @@ impl<C> SubsegmentContext<C> {
     pub fn with_header(client: C, header: Header) -> Self {
         Self {
             client,
-            header,
             name_prefix: "".to_string(),
+            in_progress: InProgress::default(),
+            parent: Parent {
+                header,
+                embedding: Embedding::Off,
+                depth: 0,
+                precursors: None,
+            },
+        }
+    }
+
+    pub(crate) fn child(
+        client: C,
+        name_prefix: String,
+        in_progress: InProgress,
+        parent: Parent,
+    ) -> Self {
+        Self {
+            client,
+            name_prefix,
+            in_progress,
+            parent,
         }
     }
 
@@ -57,13 +185,105 @@ impl<C> SubsegmentContext<C> {
     /// [`CustomNamespace`][crate::namespace::CustomNamespace] are affected.
     pub fn with_name_prefix(self, prefix: impl Into<String>) -> Self {
         Self {
-            client: self.client,
-            header: self.header,
             name_prefix: prefix.into(),
+            ..self
+        }
+    }
+
+    /// Updates the context with a given policy for in-progress documents.
+    ///
+    /// [`InProgressPolicy::Always`] by default.
+    pub fn with_in_progress_policy(self, in_progress_policy: InProgressPolicy) -> Self
+    where
+        C: Client + 'static,
+    {
+        Self {
+            in_progress: InProgress::new(in_progress_policy, &self.client),
+            ..self
+        }
+    }
+
+    /// Updates the context to embed children into their parent subsegment.
+    ///
+    /// Subsegments entered in this context are sent as usual, but children
+    /// entered in their [`SubsegmentSession::child_context`] are collected
+    /// into the parent's `subsegments` and shipped in one document when the
+    /// parent ends. Once `streaming_threshold` subsegments are buffered in a
+    /// tree, further children are sent separately.
+    ///
+    /// ```
+    /// use xray_lite::{Context as _, CustomNamespace, DaemonClient, SubsegmentContext};
+    ///
+    /// # std::env::set_var("AWS_XRAY_DAEMON_ADDRESS", "127.0.0.1:2000");
+    /// let client = DaemonClient::from_lambda_env().unwrap();
+    /// # std::env::set_var("_X_AMZN_TRACE_ID", "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1");
+    /// let context = SubsegmentContext::from_lambda_env(client)
+    ///     .unwrap()
+    ///     .with_embedded_subsegments(SubsegmentContext::<DaemonClient>::DEFAULT_STREAMING_THRESHOLD);
+    /// let session = context.enter_subsegment(CustomNamespace::new("batch"));
+    /// let children = session.child_context().unwrap();
+    /// for _ in 0..10 {
+    ///     // embedded into "batch"
+    ///     let _child = children.enter_subsegment(CustomNamespace::new("item"));
+    /// }
+    /// ```
+    pub fn with_embedded_subsegments(self, streaming_threshold: usize) -> Self {
+        Self {
+            parent: Parent {
+                embedding: Embedding::Root {
+                    streaming_threshold,
+                },
+                ..self.parent
+            },
+            ..self
+        }
+    }
+
+    /// Updates the context to record precursors of subsegments.
+    ///
+    /// A subsegment entered in this context gets, as its `precursor_ids`,
+    /// the siblings that have completed but that no other sibling has
+    /// followed yet. Thus, sequential subsegments are chained, and a
+    /// subsegment entered after parallel ones follows all of them. The same
+    /// applies to children entered in [`SubsegmentSession::child_context`].
+    ///
+    /// Use [`SubsegmentSession::precursor_id`] to link subsegments
+    /// explicitly instead.
+    ///
+    /// ```
+    /// use xray_lite::{Context as _, CustomNamespace, DaemonClient, SubsegmentContext};
+    ///
+    /// # std::env::set_var("AWS_XRAY_DAEMON_ADDRESS", "127.0.0.1:2000");
+    /// let client = DaemonClient::from_lambda_env().unwrap();
+    /// # std::env::set_var("_X_AMZN_TRACE_ID", "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1");
+    /// let context = SubsegmentContext::from_lambda_env(client)
+    ///     .unwrap()
+    ///     .with_precursors();
+    /// drop(context.enter_subsegment(CustomNamespace::new("extract")));
+    /// // follows "extract"
+    /// drop(context.enter_subsegment(CustomNamespace::new("transform")));
+    /// ```
+    pub fn with_precursors(self) -> Self {
+        Self {
+            parent: Parent {
+                precursors: Some(Precursors::default()),
+                ..self.parent
+            },
+            ..self
         }
     }
 }
 
+impl<C> SubsegmentContext<C> {
+    // a subsegment being traced by `in_subsegment` takes over if it is nested
+    // deeper in the same trace
+    fn current_parent(&self) -> Option<Parent> {
+        instrument::current_parent().filter(|p| {
+            p.header.trace_id == self.parent.header.trace_id && p.depth >= self.parent.depth
+        })
+    }
+}
+
 impl<C> Context for SubsegmentContext<C>
 where
     C: Client,
@@ -76,9 +296,17 @@ where
     {
         SubsegmentSession::new(
             self.client.clone(),
-            &self.header,
+            self.current_parent().as_ref().unwrap_or(&self.parent),
             namespace,
             &self.name_prefix,
+            self.in_progress.clone(),
+        )
+    }
+
+    fn header(&self) -> Option<Header> {
+        Some(
+            self.current_parent()
+                .map_or_else(|| self.parent.header.clone(), |p| p.header),
         )
     }
 }
@@ -122,6 +350,13 @@ where
             Self::Noop => SubsegmentSession::failed(),
         }
     }
+
+    fn header(&self) -> Option<Header> {
+        match self {
+            Self::Op(context) => context.header(),
+            Self::Noop => None,
+        }
+    }
 }
 
 impl<T> Clone for InfallibleContext<T>
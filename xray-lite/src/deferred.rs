@@ -0,0 +1,185 @@
+//! Jobs deferred to a background thread.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+use crate::hook::report_error;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// `None` if the scheduler thread could not be started
+static SCHEDULER: OnceLock<Option<Mutex<Sender<Entry>>>> = OnceLock::new();
+
+/// Job that runs on the scheduler thread after a delay unless cancelled.
+///
+/// All deferred jobs share a single scheduler thread, which is started when
+/// the first job is scheduled.
+pub(crate) struct Deferred {
+    job: Arc<Mutex<Option<Job>>>,
+}
+
+impl std::fmt::Debug for Deferred {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Deferred")
+            .field("pending", &lock(&self.job).is_some())
+            .finish()
+    }
+}
+
+impl Deferred {
+    /// Schedules a job to run after a given delay.
+    pub(crate) fn schedule(delay: Duration, job: impl FnOnce() + Send + 'static) -> Self {
+        let deferred = Self {
+            job: Arc::new(Mutex::new(Some(Box::new(job)))),
+        };
+        let entry = Entry {
+            deadline: Instant::now() + delay,
+            job: Arc::downgrade(&deferred.job),
+        };
+        if let Some(scheduler) = SCHEDULER.get_or_init(start) {
+            // the scheduler thread never exits while the sender exists
+            let _ = scheduler
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .send(entry);
+        }
+        deferred
+    }
+
+    /// Runs the job now unless it has already run.
+    pub(crate) fn run_now(&self) {
+        if let Some(job) = lock(&self.job).take() {
+            job();
+        }
+    }
+
+    /// Cancels the job unless it has already run.
+    ///
+    /// Waits for the job if it is running, so that nothing the job does
+    /// happens after this returns.
+    pub(crate) fn cancel(&self) {
+        lock(&self.job).take();
+    }
+}
+
+// scheduled job ordered by its deadline; the earliest first.
+struct Entry {
+    deadline: Instant,
+    // dropped jobs are not run
+    job: Weak<Mutex<Option<Job>>>,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+fn start() -> Option<Mutex<Sender<Entry>>> {
+    let (sender, receiver) = mpsc::channel();
+    match std::thread::Builder::new()
+        .name("xray-lite-deferred".to_string())
+        .spawn(move || run(receiver))
+    {
+        Ok(_) => Some(Mutex::new(sender)),
+        Err(e) => {
+            report_error("failed to start scheduler thread", &e.into());
+            None
+        }
+    }
+}
+
+fn run(receiver: Receiver<Entry>) {
+    let mut queue = BinaryHeap::new();
+    loop {
+        let received = match queue.peek() {
+            Some(Entry { deadline, .. }) => {
+                receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(entry) => queue.push(entry),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        let now = Instant::now();
+        while queue.peek().is_some_and(|entry| entry.deadline <= now) {
+            if let Some(job) = queue.pop().and_then(|entry| entry.job.upgrade()) {
+                // holds the lock while running so that `cancel` waits
+                let mut job = lock(&job);
+                if let Some(job) = job.take() {
+                    job();
+                }
+            }
+        }
+    }
+}
+
+fn lock(job: &Mutex<Option<Job>>) -> MutexGuard<'_, Option<Job>> {
+    job.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn deferred_should_run_after_delay() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let _deferred = Deferred::schedule(Duration::from_millis(10), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn deferred_should_not_run_once_cancelled_or_dropped() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let cancelled = Deferred::schedule(Duration::from_millis(10), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        cancelled.cancel();
+        let counter = runs.clone();
+        drop(Deferred::schedule(Duration::from_millis(10), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn deferred_should_run_once_if_run_early() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let deferred = Deferred::schedule(Duration::from_millis(10), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        deferred.run_now();
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,142 @@
+//! Embedding of subsegments into their parents.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::segment::Subsegment;
+
+/// How subsegments entered in a context are recorded.
+#[derive(Clone, Debug, Default)]
+pub(crate) enum Embedding {
+    /// Every subsegment is sent separately.
+    #[default]
+    Off,
+    /// Subsegments are sent separately, and their children are embedded.
+    Root {
+        /// Maximum number of subsegments buffered in a tree.
+        streaming_threshold: usize,
+    },
+    /// Subsegments are embedded into their parent.
+    Child(Embedded),
+}
+
+/// Collector of the completed children of a subsegment.
+#[derive(Clone, Debug)]
+pub(crate) struct Embedded {
+    // `None` once the parent has ended
+    subsegments: Arc<Mutex<Option<Vec<Subsegment>>>>,
+    tree: Arc<Tree>,
+}
+
+/// Bookkeeping shared by all the collectors in a subsegment tree.
+#[derive(Debug)]
+struct Tree {
+    buffered: AtomicUsize,
+    streaming_threshold: usize,
+}
+
+impl Embedded {
+    /// Creates a collector for the children of a root subsegment.
+    pub(crate) fn root(streaming_threshold: usize) -> Self {
+        Self {
+            subsegments: Arc::new(Mutex::new(Some(Vec::new()))),
+            tree: Arc::new(Tree {
+                buffered: AtomicUsize::new(0),
+                streaming_threshold,
+            }),
+        }
+    }
+
+    /// Creates a collector for the children of a child in the same tree.
+    pub(crate) fn child(&self) -> Self {
+        Self {
+            subsegments: Arc::new(Mutex::new(Some(Vec::new()))),
+            tree: self.tree.clone(),
+        }
+    }
+
+    /// Embeds a completed subsegment into the parent.
+    ///
+    /// Returns the subsegment back if it has to be sent separately; i.e., the
+    /// streaming threshold has been reached, or the parent has already ended.
+    pub(crate) fn embed(&self, mut subsegment: Subsegment) -> Option<Subsegment> {
+        let mut subsegments = self
+            .subsegments
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match subsegments.as_mut() {
+            Some(subsegments) if self.tree.reserve() => {
+                subsegment.trace_id = None;
+                subsegment.parent_id = None;
+                subsegment.type_.clear();
+                subsegments.push(subsegment);
+                None
+            }
+            _ => {
+                // descendants leave the buffer with the subsegment
+                self.tree
+                    .buffered
+                    .fetch_sub(count_descendants(&subsegment), Ordering::Relaxed);
+                Some(subsegment)
+            }
+        }
+    }
+
+    /// Takes the embedded subsegments.
+    ///
+    /// Subsegments completed afterwards are sent separately.
+    pub(crate) fn close(&self) -> Vec<Subsegment> {
+        self.subsegments
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .unwrap_or_default()
+    }
+}
+
+impl Tree {
+    // reserves a slot in the buffer unless the streaming threshold has been
+    // reached. the counter is shared by collectors under different parents,
+    // so the check and the increment have to be atomic.
+    fn reserve(&self) -> bool {
+        self.buffered
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |buffered| {
+                (buffered < self.streaming_threshold).then_some(buffered + 1)
+            })
+            .is_ok()
+    }
+}
+
+fn count_descendants(subsegment: &Subsegment) -> usize {
+    subsegment
+        .subsegments
+        .iter()
+        .map(|child| 1 + count_descendants(child))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embed_should_not_exceed_streaming_threshold_across_parents() {
+        let root = Embedded::root(10);
+        let parents: Vec<Embedded> = (0..8).map(|_| root.child()).collect();
+        let embedded: usize = std::thread::scope(|scope| {
+            let handles: Vec<_> = parents
+                .iter()
+                .map(|parent| {
+                    scope.spawn(move || {
+                        (0..10)
+                            .filter(|_| parent.embed(Subsegment::default()).is_none())
+                            .count()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(embedded, 10);
+        assert_eq!(root.tree.buffered.load(Ordering::Relaxed), 10);
+    }
+}
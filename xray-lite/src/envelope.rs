@@ -0,0 +1,128 @@
+//! Envelopes carrying trace headers in message payloads.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::header::Header;
+
+/// Message payload wrapped with a trace header.
+///
+/// Some messaging services, e.g., Amazon Kinesis Data Streams, have no
+/// attributes to propagate a trace header. Wrap the payload of such a
+/// message in this envelope on the producer side, and unwrap it on the
+/// consumer side to continue the trace.
+///
+/// The envelope is serialized as a JSON object like the following:
+///
+/// ```json
+/// {"AWSTraceHeader":"Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1","payload":{"order":42}}
+/// ```
+///
+/// ```
+/// use xray_lite::{Header, TraceEnvelope};
+///
+/// let header: Header = "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1"
+///     .parse()
+///     .unwrap();
+/// // producer
+/// let data = TraceEnvelope::new(Some(&header), "order-42").to_vec().unwrap();
+/// // consumer
+/// let envelope = TraceEnvelope::<String>::from_slice(&data).unwrap();
+/// assert_eq!(envelope.header().unwrap().unwrap(), header);
+/// assert_eq!(envelope.payload, "order-42");
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TraceEnvelope<T> {
+    /// Trace header if the producer was traced.
+    #[serde(
+        rename = "AWSTraceHeader",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub trace_header: Option<String>,
+    /// Payload.
+    pub payload: T,
+}
+
+impl<T> TraceEnvelope<T> {
+    /// Wraps a payload with a trace header.
+    ///
+    /// Pass [`Context::header`][crate::Context::header] to propagate the
+    /// trace of a context.
+    pub fn new(header: Option<&Header>, payload: T) -> Self {
+        Self {
+            trace_header: header.map(Header::to_string),
+            payload,
+        }
+    }
+
+    /// Parses the trace header.
+    ///
+    /// `None` if the envelope has no trace header.
+    pub fn header(&self) -> Option<Result<Header>> {
+        self.trace_header.as_deref().map(str::parse)
+    }
+
+    /// Serializes the envelope into JSON bytes.
+    pub fn to_vec(&self) -> Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Deserializes an envelope from JSON bytes.
+    pub fn from_slice<'a>(data: &'a [u8]) -> Result<Self>
+    where
+        T: Deserialize<'a>,
+    {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u32,
+        items: Vec<String>,
+    }
+
+    #[test]
+    fn envelope_should_round_trip_payload_with_trace_header() {
+        let header: Header =
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1"
+                .parse()
+                .unwrap();
+        let order = Order {
+            id: 42,
+            items: vec!["apple".to_string(), "orange".to_string()],
+        };
+        let data = TraceEnvelope::new(Some(&header), &order).to_vec().unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&data).unwrap(),
+            json!({
+                "AWSTraceHeader": header.to_string(),
+                "payload": {"id": 42, "items": ["apple", "orange"]},
+            }),
+        );
+        let envelope = TraceEnvelope::<Order>::from_slice(&data).unwrap();
+        assert_eq!(envelope.header().unwrap().unwrap(), header);
+        assert_eq!(envelope.payload, order);
+    }
+
+    #[test]
+    fn envelope_should_omit_missing_trace_header() {
+        let data = TraceEnvelope::new(None, json!({"id": 42}))
+            .to_vec()
+            .unwrap();
+        assert_eq!(data, br#"{"payload":{"id":42}}"#);
+        let envelope = TraceEnvelope::<serde_json::Value>::from_slice(&data).unwrap();
+        assert!(envelope.header().is_none());
+        assert_eq!(envelope.payload, json!({"id": 42}));
+    }
+}
@@ -10,7 +10,7 @@ use std::{
 ///
 /// A Default implementation is provided which yields the number of seconds since the epoch from
 /// the system time's `now` value
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Seconds(pub(crate) f64);
 
 impl Seconds {
@@ -52,7 +52,7 @@ impl<'de> de::Visitor<'de> for SecondsVisitor {
     type Value = Seconds;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a string value")
+        formatter.write_str("a number of seconds")
     }
     fn visit_f64<E>(self, value: f64) -> Result<Seconds, E>
     where
@@ -60,6 +60,18 @@ impl<'de> de::Visitor<'de> for SecondsVisitor {
     {
         Ok(Seconds(value))
     }
+    fn visit_u64<E>(self, value: u64) -> Result<Seconds, E>
+    where
+        E: de::Error,
+    {
+        Ok(Seconds(value as f64))
+    }
+    fn visit_i64<E>(self, value: i64) -> Result<Seconds, E>
+    where
+        E: de::Error,
+    {
+        Ok(Seconds(value as f64))
+    }
 }
 
 impl ser::Serialize for Seconds {
@@ -8,14 +8,63 @@ pub enum Error {
     #[error("missing environment variable: {0}")]
     MissingEnvVar(&'static str),
     /// I/O error.
-    #[error("IO Error")]
+    #[error("I/O error: {0}")]
     IO(#[from] IOError),
     /// JSON error.
-    #[error("Json Error")]
+    #[error("JSON error: {0}")]
     Json(#[from] JsonError),
-    /// Bad configuration.
-    #[error("bad configuration: {0}")]
-    BadConfig(String),
+    /// Malformed trace ID.
+    ///
+    /// A trace ID must be in the form `1-{8 hex digits}-{24 hex digits}`.
+    #[error("invalid trace ID: {0:?}")]
+    InvalidTraceId(String),
+    /// Malformed segment ID.
+    ///
+    /// A segment ID must be 16 hex digits.
+    #[error("invalid segment ID: {0:?}")]
+    InvalidSegmentId(String),
+    /// Malformed field in a trace header.
+    #[error("invalid trace header field: {field:?}")]
+    InvalidHeaderField {
+        /// Field as it appeared in the header.
+        field: String,
+        /// Cause; e.g., [`Error::InvalidTraceId`] for the `Root` field.
+        #[source]
+        source: Option<Box<Error>>,
+    },
+    /// Malformed, unresolvable, or unsupported X-Ray daemon address.
+    #[error("invalid X-Ray daemon address: {address:?}")]
+    InvalidDaemonAddress {
+        /// Address as given.
+        address: String,
+        /// Cause; e.g., a failure in resolving the hostname.
+        #[source]
+        source: Option<IOError>,
+    },
+    /// Invalid segment document.
+    #[error("invalid document: {0}")]
+    InvalidDocument(String),
+    /// Document too large to send even after splitting.
+    #[error("document too large: {size} bytes exceeds {max_size} bytes")]
+    DocumentTooLarge {
+        /// Size of the document in bytes.
+        size: usize,
+        /// Maximum size of a document in bytes.
+        max_size: usize,
+    },
+    /// Queue of documents to send is full.
+    #[error("queue full: {capacity} documents are pending")]
+    QueueFull {
+        /// Capacity of the queue.
+        capacity: usize,
+    },
+    /// Transport to send documents has been closed.
+    #[error("transport closed")]
+    TransportClosed {
+        /// Cause if any; e.g., a connection reset.
+        #[source]
+        source: Option<IOError>,
+    },
 }
 
 /// Type alias for Results which may return [`Error`].
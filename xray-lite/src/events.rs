@@ -0,0 +1,303 @@
+//! Helpers for records of Lambda event sources.
+//!
+//! When a Lambda function processes a batch of records, `_X_AMZN_TRACE_ID`
+//! refers to the invocation while every record may carry the trace header of
+//! its producer. The helpers in this module record a subsegment per record
+//! in the invocation trace, and link it to the producer's trace with the
+//! following annotations so that you can search for both sides:
+//! - `event_source`: source of the record; e.g., `"aws:sqs"`
+//! - `message_id`: ID of the record
+//! - `linked_trace_id`: trace ID of the producer
+//!
+//! The full trace header of the producer is recorded in the metadata
+//! `AWSTraceHeader`.
+//!
+//! ```
+//! use aws_lambda_events::event::sqs::SqsEvent;
+//! use xray_lite::events::trace_record_result;
+//! use xray_lite::{DaemonClient, SubsegmentContext};
+//!
+//! fn handle(context: &SubsegmentContext<DaemonClient>, event: &SqsEvent) {
+//!     for message in &event.records {
+//!         let _ = trace_record_result(context, message, || process(message.body.as_deref()));
+//!     }
+//! }
+//!
+//! fn process(body: Option<&str>) -> Result<(), String> {
+//!     // ...
+//!     Ok(())
+//! }
+//! #
+//! # std::env::set_var("AWS_XRAY_DAEMON_ADDRESS", "127.0.0.1:2000");
+//! # std::env::set_var("_X_AMZN_TRACE_ID", "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1");
+//! # let context = SubsegmentContext::from_lambda_env(DaemonClient::from_lambda_env().unwrap()).unwrap();
+//! # handle(&context, &SqsEvent::default());
+//! ```
+
+use aws_lambda_events::event::eventbridge::EventBridgeEvent;
+use aws_lambda_events::event::sns::SnsRecord;
+use aws_lambda_events::event::sqs::SqsMessage;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Display;
+
+use crate::context::Context;
+use crate::error::Result;
+use crate::header::Header;
+use crate::hook::report_error;
+use crate::namespace::CustomNamespace;
+use crate::session::SubsegmentSession;
+
+/// Record of a Lambda event source.
+pub trait EventRecord {
+    /// Source of the record; e.g., `"aws:sqs"`.
+    fn event_source(&self) -> &str;
+
+    /// Name of the subsegment for the record; e.g., the name of the queue.
+    fn subsegment_name(&self) -> String;
+
+    /// ID of the record; e.g., the message ID.
+    fn record_id(&self) -> Option<&str>;
+
+    /// Parses the trace header of the producer.
+    ///
+    /// `None` if the record carries no trace header.
+    fn trace_header(&self) -> Option<Result<Header>>;
+}
+
+impl EventRecord for SqsMessage {
+    fn event_source(&self) -> &str {
+        self.event_source.as_deref().unwrap_or("aws:sqs")
+    }
+
+    fn subsegment_name(&self) -> String {
+        arn_resource(self.event_source_arn.as_deref()).unwrap_or_else(|| "SQS".to_string())
+    }
+
+    fn record_id(&self) -> Option<&str> {
+        self.message_id.as_deref()
+    }
+
+    fn trace_header(&self) -> Option<Result<Header>> {
+        Header::from_attributes(&self.attributes)
+    }
+}
+
+impl EventRecord for SnsRecord {
+    fn event_source(&self) -> &str {
+        &self.event_source
+    }
+
+    fn subsegment_name(&self) -> String {
+        arn_resource(Some(&self.sns.topic_arn)).unwrap_or_else(|| "SNS".to_string())
+    }
+
+    fn record_id(&self) -> Option<&str> {
+        Some(&self.sns.message_id)
+    }
+
+    fn trace_header(&self) -> Option<Result<Header>> {
+        self.sns
+            .message_attributes
+            .get(Header::MESSAGE_ATTRIBUTE)
+            .map(|attribute| attribute.value.parse())
+    }
+}
+
+/// EventBridge does not pass the trace header of the producer in an event,
+/// but continues the trace in the invocation itself. So an event is not
+/// linked to another trace.
+impl<T> EventRecord for EventBridgeEvent<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn event_source(&self) -> &str {
+        "aws:events"
+    }
+
+    fn subsegment_name(&self) -> String {
+        self.source.clone()
+    }
+
+    fn record_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    fn trace_header(&self) -> Option<Result<Header>> {
+        None
+    }
+}
+
+/// Creates a namespace for a record.
+///
+/// The namespace has the annotations and metadata described in the
+/// [module documentation][self].
+pub fn record_namespace(record: &impl EventRecord) -> CustomNamespace {
+    let mut namespace = CustomNamespace::new(record.subsegment_name());
+    namespace.annotation("event_source", record.event_source());
+    if let Some(id) = record.record_id() {
+        namespace.annotation("message_id", id);
+    }
+    match record.trace_header() {
+        Some(Ok(header)) => {
+            namespace
+                .annotation("linked_trace_id", header.trace_id.to_string())
+                .metadata(Header::MESSAGE_ATTRIBUTE, header.to_string());
+        }
+        Some(Err(e)) => report_error("failed to parse trace header of record", &e),
+        None => (),
+    }
+    namespace
+}
+
+/// Enters a subsegment for a record.
+pub fn enter_record<X, R>(context: &X, record: &R) -> SubsegmentSession<X::Client, CustomNamespace>
+where
+    X: Context,
+    R: EventRecord,
+{
+    context.enter_subsegment(record_namespace(record))
+}
+
+/// Runs a closure processing a record in a subsegment for the record.
+///
+/// See [`Context::in_subsegment`].
+pub fn trace_record<X, R, F, O>(context: &X, record: &R, f: F) -> O
+where
+    X: Context,
+    R: EventRecord,
+    F: FnOnce() -> O,
+{
+    context.in_subsegment(record_namespace(record), f)
+}
+
+/// Runs a closure processing a record in a subsegment for the record, and
+/// records an error the closure returns.
+///
+/// See [`Context::in_subsegment_result`].
+pub fn trace_record_result<X, R, F, T, E>(
+    context: &X,
+    record: &R,
+    f: F,
+) -> std::result::Result<T, E>
+where
+    X: Context,
+    R: EventRecord,
+    F: FnOnce() -> std::result::Result<T, E>,
+    E: Display,
+{
+    context.in_subsegment_result(record_namespace(record), f)
+}
+
+// resource name at the end of an ARN; e.g., the queue name.
+fn arn_resource(arn: Option<&str>) -> Option<String> {
+    arn?.rsplit(':')
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::session::InProgressPolicy;
+    use crate::testing::{context, RecordingClient};
+
+    const PRODUCER: &str =
+        "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1";
+
+    fn sqs_message(id: &str) -> SqsMessage {
+        SqsMessage {
+            message_id: Some(id.to_string()),
+            event_source: Some("aws:sqs".to_string()),
+            event_source_arn: Some("arn:aws:sqs:us-east-1:123456789012:orders".to_string()),
+            attributes: HashMap::from([(
+                Header::MESSAGE_ATTRIBUTE.to_string(),
+                PRODUCER.to_string(),
+            )]),
+            ..SqsMessage::default()
+        }
+    }
+
+    #[test]
+    fn trace_record_should_link_sqs_messages_to_producer() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_in_progress_policy(InProgressPolicy::Never);
+        for id in ["m1", "m2"] {
+            let output: std::result::Result<(), String> =
+                trace_record_result(&context, &sqs_message(id), || Err("boom".to_string()));
+            assert!(output.is_err());
+        }
+        let documents = client.documents();
+        assert_eq!(documents.len(), 2);
+        let document = &documents[1];
+        assert_eq!(document["name"], "orders");
+        assert_eq!(document["trace_id"], "1-65dfb5a1-0123456789abcdef01234567");
+        assert_eq!(document["fault"], true);
+        let annotations = &document["annotations"];
+        assert_eq!(annotations["event_source"], "aws:sqs");
+        assert_eq!(annotations["message_id"], "m2");
+        assert_eq!(
+            annotations["linked_trace_id"],
+            "1-5759e988-bd862e3fe1be46a994272793"
+        );
+        assert_eq!(document["metadata"]["default"]["AWSTraceHeader"], PRODUCER);
+    }
+
+    #[test]
+    fn trace_record_should_return_any_output() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_in_progress_policy(InProgressPolicy::Never);
+        let length = trace_record(&context, &sqs_message("m1"), || "body".len());
+        assert_eq!(length, 4);
+        let documents = client.documents();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0]["annotations"]["message_id"], "m1");
+    }
+
+    #[test]
+    fn sns_record_should_have_trace_header_of_message_attribute() {
+        let record: SnsRecord = serde_json::from_value(serde_json::json!({
+            "EventSource": "aws:sns",
+            "EventVersion": "1.0",
+            "EventSubscriptionArn": "arn:aws:sns:us-east-1:123456789012:events:sub",
+            "Sns": {
+                "Type": "Notification",
+                "MessageId": "n1",
+                "TopicArn": "arn:aws:sns:us-east-1:123456789012:events",
+                "Timestamp": "2024-01-01T00:00:00.000Z",
+                "SignatureVersion": "1",
+                "Signature": "",
+                "SigningCertUrl": "",
+                "UnsubscribeUrl": "",
+                "Message": "{}",
+                "MessageAttributes": {
+                    "AWSTraceHeader": {"Type": "String", "Value": PRODUCER},
+                },
+            },
+        }))
+        .unwrap();
+        assert_eq!(record.subsegment_name(), "events");
+        assert_eq!(record.record_id(), Some("n1"));
+        assert_eq!(
+            record.trace_header().unwrap().unwrap(),
+            PRODUCER.parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn eventbridge_event_should_not_be_linked() {
+        let event: EventBridgeEvent = serde_json::from_value(serde_json::json!({
+            "id": "e1",
+            "detail-type": "Order Placed",
+            "source": "com.example.orders",
+            "detail": {},
+        }))
+        .unwrap();
+        assert_eq!(event.subsegment_name(), "com.example.orders");
+        assert_eq!(event.record_id(), Some("e1"));
+        assert!(event.trace_header().is_none());
+    }
+}
@@ -1,10 +1,12 @@
 //! X-Ray [tracing header](https://docs.aws.amazon.com/xray/latest/devguide/xray-concepts.html?shortFooter=true#xray-concepts-tracingheader)
 //! parser
 
-use crate::{SegmentId, TraceId};
+use crate::{Error, SegmentId, TraceId};
 use std::{
+    borrow::Borrow,
     collections::HashMap,
     fmt::{self, Display},
+    hash::{BuildHasher, Hash},
     str::FromStr,
 };
 
@@ -72,6 +74,12 @@ impl Header {
     /// HTTP header values should be the Display serialization of Header structs
     pub const NAME: &'static str = "X-Amzn-Trace-Id";
 
+    /// Name of the message attribute that carries the trace header.
+    ///
+    /// Amazon SQS sets this system attribute of a message, and AWS Lambda
+    /// passes it in the `attributes` of an SQS event record.
+    pub const MESSAGE_ATTRIBUTE: &'static str = "AWSTraceHeader";
+
     /// Creates a new Header with a given trace ID.
     pub fn new(trace_id: TraceId) -> Self {
         Header {
@@ -100,6 +108,33 @@ impl Header {
         }
     }
 
+    /// Parses the trace header in message attributes.
+    ///
+    /// Looks up [`Header::MESSAGE_ATTRIBUTE`]; e.g., in the `attributes` of an
+    /// SQS event record. `None` if the attribute is missing.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use xray_lite::Header;
+    ///
+    /// let attributes = HashMap::from([(
+    ///     "AWSTraceHeader".to_string(),
+    ///     "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1".to_string(),
+    /// )]);
+    /// let header = Header::from_attributes(&attributes).unwrap().unwrap();
+    /// assert_eq!(header.trace_id.to_string(), "1-5759e988-bd862e3fe1be46a994272793");
+    /// ```
+    pub fn from_attributes<K, V, S>(attributes: &HashMap<K, V, S>) -> Option<Result<Self, Error>>
+    where
+        K: Borrow<str> + Hash + Eq,
+        V: AsRef<str>,
+        S: BuildHasher,
+    {
+        attributes
+            .get(Self::MESSAGE_ATTRIBUTE)
+            .map(|value| value.as_ref().parse())
+    }
+
     /// Inserts a key-value pair into the additional data map.
     pub fn insert_data(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
         self.additional_data.insert(key.into(), value.into());
@@ -108,20 +143,27 @@ impl Header {
 }
 
 impl FromStr for Header {
-    type Err = String;
+    type Err = Error;
+
+    /// Parses a trace header.
+    ///
+    /// Fails with [`Error::InvalidHeaderField`] if a field is not in the form
+    /// `key=value`, or the `Root` or `Parent` field is malformed.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |field: &str, source: Option<Error>| Error::InvalidHeaderField {
+            field: field.into(),
+            source: source.map(Box::new),
+        };
         s.split(';')
             .try_fold(Header::default(), |mut header, line| {
                 if let Some(trace_id) = line.strip_prefix("Root=") {
-                    header.trace_id = TraceId::Rendered(trace_id.into())
+                    header.trace_id = trace_id.parse().map_err(|e| invalid(line, Some(e)))?;
                 } else if let Some(parent_id) = line.strip_prefix("Parent=") {
-                    header.parent_id = Some(SegmentId::Rendered(parent_id.into()))
+                    header.parent_id = Some(parent_id.parse().map_err(|e| invalid(line, Some(e)))?);
                 } else if line.starts_with("Sampled=") {
                     header.sampling_decision = line.into();
                 } else if !line.starts_with("Self=") {
-                    let (key, value) = line
-                        .split_once('=')
-                        .ok_or_else(|| format!("invalid key=value: no `=` found in `{}`", s))?;
+                    let (key, value) = line.split_once('=').ok_or_else(|| invalid(line, None))?;
                     header.additional_data.insert(key.into(), value.into());
                 }
                 Ok(header)
@@ -152,33 +194,37 @@ mod tests {
     fn parse_with_parent_from_str() {
         assert_eq!(
             "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1"
-                .parse::<Header>(),
-            Ok(Header {
+                .parse::<Header>()
+                .unwrap(),
+            Header {
                 trace_id: TraceId::Rendered("1-5759e988-bd862e3fe1be46a994272793".into()),
                 parent_id: Some(SegmentId::Rendered("53995c3f42cd8ad8".into())),
                 sampling_decision: SamplingDecision::Sampled,
                 ..Header::default()
-            })
+            }
         )
     }
     #[test]
     fn parse_no_parent_from_str() {
         assert_eq!(
-            "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1".parse::<Header>(),
-            Ok(Header {
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1"
+                .parse::<Header>()
+                .unwrap(),
+            Header {
                 trace_id: TraceId::Rendered("1-5759e988-bd862e3fe1be46a994272793".into()),
                 parent_id: None,
                 sampling_decision: SamplingDecision::Sampled,
                 ..Header::default()
-            })
+            }
         )
     }
     #[test]
     fn parse_with_additional_data_from_str() {
         assert_eq!(
             "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1;Lineage=01234567:0;Unknown=unknown"
-                .parse::<Header>(),
-            Ok(Header {
+                .parse::<Header>()
+                .unwrap(),
+            Header {
                 trace_id: TraceId::Rendered("1-5759e988-bd862e3fe1be46a994272793".into()),
                 parent_id: Some(SegmentId::Rendered("53995c3f42cd8ad8".into())),
                 sampling_decision: SamplingDecision::Sampled,
@@ -186,10 +232,28 @@ mod tests {
                     ("Lineage".into(), "01234567:0".into()),
                     ("Unknown".into(), "unknown".into()),
                 ].into_iter().collect()
-            })
+            }
         )
     }
 
+    #[test]
+    fn parse_invalid_fields_from_str() {
+        assert!(matches!(
+            "Root=1-5759e988;Sampled=1".parse::<Header>(),
+            Err(Error::InvalidHeaderField { field, source: Some(source) })
+                if field == "Root=1-5759e988" && matches!(*source, Error::InvalidTraceId(_))
+        ));
+        assert!(matches!(
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=xyz".parse::<Header>(),
+            Err(Error::InvalidHeaderField { source: Some(source), .. })
+                if matches!(*source, Error::InvalidSegmentId(_))
+        ));
+        assert!(matches!(
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Lineage".parse::<Header>(),
+            Err(Error::InvalidHeaderField { field, source: None }) if field == "Lineage"
+        ));
+    }
+
     #[test]
     fn displays_as_header() {
         let header = Header {
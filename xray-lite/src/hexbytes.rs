@@ -12,6 +12,11 @@ impl fmt::LowerHex for Bytes<'_> {
     }
 }
 
+/// Returns whether a value consists of `length` hex digits.
+pub(crate) fn is_hex(value: &str, length: usize) -> bool {
+    value.len() == length && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
 #[cfg(test)]
 mod tests {
     use super::Bytes;
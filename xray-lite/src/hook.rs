@@ -0,0 +1,72 @@
+//! Hook for errors that cannot be returned to the caller.
+
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::error::Error;
+
+type ErrorHook = Arc<dyn Fn(&str, &Error) + Send + Sync>;
+
+static ERROR_HOOK: RwLock<Option<ErrorHook>> = RwLock::new(None);
+
+/// Sets the hook for errors that cannot be returned to the caller.
+///
+/// Some errors occur where there is no caller to return them to; e.g., when a
+/// [`SubsegmentSession`][crate::SubsegmentSession] fails to send a document
+/// while it is dropped. Such errors are passed to the hook along with a short
+/// description of what failed. They are written to stderr by default.
+///
+/// ```
+/// use xray_lite::set_error_hook;
+///
+/// set_error_hook(|what, error| {
+///     // forward to your logger instead of stderr
+///     eprintln!("xray-lite: {what}: {error}");
+/// });
+/// ```
+pub fn set_error_hook<F>(hook: F)
+where
+    F: Fn(&str, &Error) + Send + Sync + 'static,
+{
+    *ERROR_HOOK.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(hook));
+}
+
+/// Restores the default error hook that writes errors to stderr.
+pub fn reset_error_hook() {
+    *ERROR_HOOK.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Reports an error to the error hook.
+///
+/// This is meant for implementations of [`Client`][crate::Client] that
+/// encounter errors in the background.
+pub fn report_error(what: &str, error: &Error) {
+    let hook = ERROR_HOOK
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    match hook {
+        Some(hook) => hook(what, error),
+        None => eprintln!("{what}: {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    #[test]
+    fn report_error_should_call_error_hook() {
+        static REPORTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        set_error_hook(|what, error| {
+            REPORTED.lock().unwrap().push(format!("{what}: {error}"));
+        });
+        report_error("failed to test", &Error::InvalidTraceId("hook".into()));
+        reset_error_hook();
+        assert!(REPORTED
+            .lock()
+            .unwrap()
+            .contains(&r#"failed to test: invalid trace ID: "hook""#.to_string()));
+    }
+}
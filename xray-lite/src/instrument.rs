@@ -0,0 +1,398 @@
+//! Instrumentation of closures and futures with subsegments.
+
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+
+use crate::client::Client;
+use crate::context::{Context, Parent};
+use crate::hook::report_error;
+use crate::namespace::Namespace;
+use crate::session::{Cancelled, Outcome, SubsegmentSession};
+
+thread_local! {
+    static CURRENT_PARENT: RefCell<Option<Parent>> = const { RefCell::new(None) };
+}
+
+/// Returns the parent of subsegments entered on this thread, if any.
+pub(crate) fn current_parent() -> Option<Parent> {
+    CURRENT_PARENT.with(|current| current.borrow().clone())
+}
+
+/// Makes a session the current parent until the returned guard is dropped.
+///
+/// The current parent does not change if the session is non-operational.
+pub(crate) fn enter_parent<C, N>(session: &SubsegmentSession<C, N>) -> ParentGuard
+where
+    C: Client,
+    N: Namespace + Send + Sync,
+{
+    let previous = session
+        .as_parent()
+        .map(|parent| CURRENT_PARENT.with(|current| current.replace(Some(parent))));
+    ParentGuard { previous }
+}
+
+/// Restores the previous parent when dropped.
+pub(crate) struct ParentGuard {
+    // `None` if the current parent has not been replaced
+    previous: Option<Option<Parent>>,
+}
+
+impl Drop for ParentGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            CURRENT_PARENT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+}
+
+/// Runs a closure in a new subsegment that ends with the outcome picked from
+/// the output of the closure.
+pub(crate) fn run_in_subsegment<X, N, F, R>(
+    context: &X,
+    namespace: N,
+    f: F,
+    outcome: fn(&R) -> &dyn Outcome,
+) -> R
+where
+    X: Context + ?Sized,
+    N: Namespace + Send + Sync,
+    F: FnOnce() -> R,
+{
+    let session = context.enter_subsegment(namespace);
+    let output = {
+        let _current = enter_parent(&session);
+        f()
+    };
+    if let Err(e) = session.end_with(outcome(&output)) {
+        report_error("failed to end subsegment", &e);
+    }
+    output
+}
+
+/// Extension of futures to trace them in subsegments.
+///
+/// ```
+/// use xray_lite::{Context as _, CustomNamespace, DaemonClient, Instrument as _, SubsegmentContext};
+///
+/// # std::env::set_var("AWS_XRAY_DAEMON_ADDRESS", "127.0.0.1:2000");
+/// let client = DaemonClient::from_lambda_env().unwrap();
+/// # std::env::set_var("_X_AMZN_TRACE_ID", "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1");
+/// let context = SubsegmentContext::from_lambda_env(client).unwrap();
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let answer = async {
+///     // a child of "fetch"
+///     let _child = context.enter_subsegment(CustomNamespace::new("parse"));
+///     "42".parse::<u32>()
+/// }
+/// .in_subsegment_result(&context, CustomNamespace::new("fetch"))
+/// .await;
+/// assert_eq!(answer, Ok(42));
+/// # });
+/// ```
+pub trait Instrument: Future + Sized {
+    /// Traces this future in a new subsegment.
+    ///
+    /// The subsegment is entered when the future is polled for the first
+    /// time. While the future is polled, subsegments entered in any context
+    /// of the same trace become children of the subsegment. The subsegment
+    /// ends when the future completes. Use [`Instrument::in_subsegment_result`]
+    /// to record an error the future resolves to. If the future is dropped
+    /// before it completes, the subsegment ends with a `cancelled` annotation.
+    fn in_subsegment<X, N>(self, context: &X, namespace: N) -> Instrumented<Self, X, N>
+    where
+        X: Context + Clone,
+        N: Namespace + Send + Sync,
+    {
+        Instrumented {
+            future: self,
+            pending: Some((context.clone(), namespace)),
+            session: None,
+            outcome: |_| &(),
+        }
+    }
+
+    /// Traces this future resolving to a `Result` in a new subsegment.
+    ///
+    /// The same as [`Instrument::in_subsegment`], except that the subsegment
+    /// ends with the output of the future; i.e., an `Err` marks the
+    /// subsegment as a fault and is recorded in the cause. See
+    /// [`SubsegmentSession::end_with`].
+    fn in_subsegment_result<X, N, T, E>(self, context: &X, namespace: N) -> Instrumented<Self, X, N>
+    where
+        Self: Future<Output = std::result::Result<T, E>>,
+        X: Context + Clone,
+        N: Namespace + Send + Sync,
+        E: Display,
+    {
+        Instrumented {
+            future: self,
+            pending: Some((context.clone(), namespace)),
+            session: None,
+            outcome: |output| output,
+        }
+    }
+}
+
+impl<F> Instrument for F where F: Future {}
+
+/// Future traced in a subsegment.
+///
+/// Created by [`Instrument::in_subsegment`] or
+/// [`Instrument::in_subsegment_result`].
+#[derive(Debug)]
+pub struct Instrumented<F, X, N>
+where
+    F: Future,
+    X: Context,
+    N: Namespace + Send + Sync,
+{
+    future: F,
+    // context and namespace until the subsegment is entered
+    pending: Option<(X, N)>,
+    // `None` before the first poll and after completion
+    session: Option<SubsegmentSession<X::Client, N>>,
+    // outcome to end the subsegment with
+    outcome: fn(&F::Output) -> &dyn Outcome,
+}
+
+impl<F, X, N> Future for Instrumented<F, X, N>
+where
+    F: Future,
+    X: Context,
+    N: Namespace + Send + Sync,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is structurally pinned and never moved; the other
+        // fields are not pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some((context, namespace)) = this.pending.take() {
+            this.session = Some(context.enter_subsegment(namespace));
+        }
+        let output = {
+            let _current = this.session.as_ref().map(enter_parent);
+            // SAFETY: see above.
+            let future = unsafe { Pin::new_unchecked(&mut this.future) };
+            match future.poll(cx) {
+                Poll::Ready(output) => output,
+                Poll::Pending => return Poll::Pending,
+            }
+        };
+        if let Some(session) = this.session.take() {
+            if let Err(e) = session.end_with((this.outcome)(&output)) {
+                report_error("failed to end subsegment", &e);
+            }
+        }
+        Poll::Ready(output)
+    }
+}
+
+impl<F, X, N> Drop for Instrumented<F, X, N>
+where
+    F: Future,
+    X: Context,
+    N: Namespace + Send + Sync,
+{
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            if let Err(e) = session.end_with(&Cancelled) {
+                report_error("failed to end subsegment", &e);
+            }
+        }
+    }
+}
+
+/// Runs a closure returning any value in a subsegment.
+#[doc(hidden)]
+pub fn traced<X, N, F, R>(context: X, namespace: N, f: F) -> R
+where
+    X: Context,
+    N: Namespace + Send + Sync,
+    F: FnOnce() -> R,
+{
+    context.in_subsegment(namespace, f)
+}
+
+/// Runs a closure returning a `Result` in a subsegment.
+#[doc(hidden)]
+pub fn traced_result<X, N, F, T, E>(context: X, namespace: N, f: F) -> std::result::Result<T, E>
+where
+    X: Context,
+    N: Namespace + Send + Sync,
+    F: FnOnce() -> std::result::Result<T, E>,
+    E: Display,
+{
+    context.in_subsegment_result(namespace, f)
+}
+
+/// Awaits a future resolving to any value in a subsegment.
+#[doc(hidden)]
+pub async fn traced_async<X, N, F>(context: X, namespace: N, future: F) -> F::Output
+where
+    X: Context + Clone,
+    N: Namespace + Send + Sync,
+    F: Future,
+{
+    future.in_subsegment(&context, namespace).await
+}
+
+/// Awaits a future resolving to a `Result` in a subsegment.
+#[doc(hidden)]
+pub async fn traced_async_result<X, N, F, T, E>(
+    context: X,
+    namespace: N,
+    future: F,
+) -> std::result::Result<T, E>
+where
+    X: Context + Clone,
+    N: Namespace + Send + Sync,
+    F: Future<Output = std::result::Result<T, E>>,
+    E: Display,
+{
+    future.in_subsegment_result(&context, namespace).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{pending, ready};
+
+    use super::*;
+    use crate::context::SubsegmentContext;
+    use crate::namespace::CustomNamespace;
+    use crate::session::InProgressPolicy;
+    use crate::testing::{self, RecordingClient};
+
+    fn context(client: &RecordingClient) -> SubsegmentContext<RecordingClient> {
+        testing::context(client).with_in_progress_policy(InProgressPolicy::Never)
+    }
+
+    #[test]
+    fn in_subsegment_should_parent_subsegments_entered_in_closure() {
+        let client = RecordingClient::default();
+        let context = context(&client);
+        let output: std::result::Result<(), String> =
+            context.in_subsegment_result(CustomNamespace::new("outer"), || {
+                drop(context.enter_subsegment(CustomNamespace::new("inner")));
+                Err("boom".to_string())
+            });
+        assert!(output.is_err());
+        drop(context.enter_subsegment(CustomNamespace::new("after")));
+        let documents = client.documents();
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents[0]["name"], "inner");
+        assert_eq!(documents[1]["name"], "outer");
+        assert_eq!(documents[0]["parent_id"], documents[1]["id"]);
+        assert_eq!(documents[1]["parent_id"], "0123456789abcdef");
+        assert_eq!(documents[1]["fault"], true);
+        assert_eq!(documents[1]["cause"]["exceptions"][0]["message"], "boom");
+        assert_eq!(documents[2]["parent_id"], "0123456789abcdef");
+    }
+
+    #[test]
+    fn in_subsegment_should_return_any_output_without_recording_it() {
+        let client = RecordingClient::default();
+        let context = context(&client);
+        assert_eq!(
+            context.in_subsegment(CustomNamespace::new("answer"), || 42),
+            42
+        );
+        let output =
+            context.in_subsegment(CustomNamespace::new("ignored"), || Err::<(), _>("boom"));
+        assert!(output.is_err());
+        let documents = client.documents();
+        assert_eq!(documents.len(), 2);
+        assert!(documents.iter().all(|d| d.get("fault").is_none()));
+    }
+
+    #[test]
+    fn in_subsegment_should_respect_explicit_child_context() {
+        let client = RecordingClient::default();
+        let context = context(&client);
+        context.in_subsegment(CustomNamespace::new("outer"), || {
+            let session = context.enter_subsegment(CustomNamespace::new("middle"));
+            drop(
+                session
+                    .child_context()
+                    .unwrap()
+                    .enter_subsegment(CustomNamespace::new("inner")),
+            );
+        });
+        let documents = client.documents();
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents[0]["name"], "inner");
+        assert_eq!(documents[1]["name"], "middle");
+        assert_eq!(documents[0]["parent_id"], documents[1]["id"]);
+        assert_eq!(documents[1]["parent_id"], documents[2]["id"]);
+    }
+
+    #[test]
+    fn context_header_should_follow_current_parent() {
+        let client = RecordingClient::default();
+        let context = context(&client);
+        assert_eq!(
+            context.header().unwrap().to_string(),
+            "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1",
+        );
+        let mut parent_id = None;
+        context.in_subsegment(CustomNamespace::new("outer"), || {
+            parent_id = context.header().unwrap().parent_id;
+        });
+        assert_eq!(client.documents()[0]["id"], parent_id.unwrap().to_string());
+    }
+
+    #[tokio::test]
+    async fn instrumented_future_should_enter_on_first_poll() {
+        let client = RecordingClient::default();
+        let context = context(&client);
+        let future = async {
+            drop(context.enter_subsegment(CustomNamespace::new("inner")));
+            tokio::task::yield_now().await;
+            drop(context.enter_subsegment(CustomNamespace::new("resumed")));
+        }
+        .in_subsegment(&context, CustomNamespace::new("outer"));
+        assert!(client.documents().is_empty());
+        future.await;
+        let documents = client.documents();
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents[2]["name"], "outer");
+        assert_eq!(documents[0]["parent_id"], documents[2]["id"]);
+        assert_eq!(documents[1]["parent_id"], documents[2]["id"]);
+        assert!(current_parent().is_none());
+    }
+
+    #[tokio::test]
+    async fn instrumented_future_should_record_error() {
+        let client = RecordingClient::default();
+        let context = context(&client);
+        let output = ready("x".parse::<u32>())
+            .in_subsegment_result(&context, CustomNamespace::new("parse"))
+            .await;
+        assert!(output.is_err());
+        let output = ready(42)
+            .in_subsegment(&context, CustomNamespace::new("answer"))
+            .await;
+        assert_eq!(output, 42);
+        let documents = client.documents();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0]["fault"], true);
+        assert!(documents[1].get("fault").is_none());
+    }
+
+    #[tokio::test]
+    async fn instrumented_future_should_mark_cancellation() {
+        let client = RecordingClient::default();
+        let context = context(&client);
+        let future = pending::<()>().in_subsegment(&context, CustomNamespace::new("slow"));
+        let timeout = tokio::time::timeout(std::time::Duration::from_millis(1), future).await;
+        assert!(timeout.is_err());
+        let documents = client.documents();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0]["annotations"]["cancelled"], true);
+        assert!(documents[0].get("fault").is_none());
+    }
+}
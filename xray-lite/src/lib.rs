@@ -115,29 +115,72 @@
 //! This crate is based on the [great work](https://github.com/softprops/xray)
 //! by [Doug Tangren (softprops)](https://github.com/softprops).
 
+#[cfg(feature = "tokio")]
+mod async_client;
+mod builder;
 mod client;
+mod combinator;
 mod context;
+mod deferred;
+mod embedded;
+mod envelope;
 mod epoch;
 mod error;
+#[cfg(feature = "lambda-events")]
+pub mod events;
 mod header;
 mod hexbytes;
+mod hook;
+mod instrument;
 mod lambda;
 mod namespace;
+mod precursor;
+mod redaction;
+pub mod resource;
 mod segment;
 mod segment_id;
 mod session;
+mod split;
+pub mod sql;
+mod stats;
+#[cfg(any(test, feature = "test-util"))]
+#[doc(hidden)]
+pub mod testing;
 mod trace_id;
+mod writer;
+
+#[cfg(feature = "tokio")]
+pub use crate::async_client::{AsyncClient, AsyncClientBridge, AsyncDaemonClient};
+#[cfg(feature = "macros")]
+pub use xray_lite_macros::traced;
 
 pub use crate::{
+    builder::{
+        AwsOperationBuilder, CauseBuilder, HttpBuilder, RequestBuilder, ResponseBuilder,
+        SegmentBuilder, SqlBuilder, SubsegmentBuilder,
+    },
     client::{Client, DaemonClient, InfallibleClient, IntoInfallibleClient},
+    combinator::{Filter, Map, Tee},
     context::{Context, InfallibleContext, IntoInfallibleContext, SubsegmentContext},
+    envelope::TraceEnvelope,
     epoch::Seconds,
     error::{Error, Result},
     header::{Header, SamplingDecision},
+    hook::{report_error, reset_error_hook, set_error_hook},
+    instrument::{Instrument, Instrumented},
     lambda::header,
-    namespace::{AwsNamespace, CustomNamespace, Namespace, RemoteNamespace},
+    namespace::{AwsNamespace, CustomNamespace, Namespace, RemoteNamespace, SqlNamespace},
+    redaction::Redaction,
     segment::*,
     segment_id::SegmentId,
-    session::SubsegmentSession,
+    session::{DetachedSession, InProgressPolicy, Outcome, SessionScope, SubsegmentSession},
+    stats::{ClientStats, StatsSnapshot},
     trace_id::TraceId,
+    writer::{RotatingFile, WriterClient},
 };
+
+#[doc(hidden)]
+pub mod __private {
+    //! Items used by the code generated by [`traced`][crate::traced].
+    pub use crate::instrument::{traced, traced_async, traced_async_result, traced_result};
+}
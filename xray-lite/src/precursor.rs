@@ -0,0 +1,43 @@
+//! Tracking of precursor subsegments.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::segment::Subsegment;
+
+/// Tracker of the completed siblings that no later sibling has followed yet.
+///
+/// A subsegment entered with a tracker takes the current frontier as its
+/// precursors. When it ends, it replaces its precursors in the frontier.
+/// Sequential siblings are thereby chained, and a sibling entered after
+/// parallel ones ends up with all of them as precursors.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Precursors {
+    // IDs of the subsegments in the frontier
+    frontier: Arc<Mutex<Vec<String>>>,
+}
+
+impl Precursors {
+    /// Records the frontier as the precursors of a subsegment being entered.
+    pub(crate) fn enter(&self, subsegment: &mut Subsegment) {
+        let frontier = self.lock().clone();
+        if !frontier.is_empty() {
+            subsegment
+                .precursor_ids
+                .get_or_insert_with(Vec::new)
+                .extend(frontier);
+        }
+    }
+
+    /// Moves the frontier past a completed subsegment.
+    pub(crate) fn complete(&self, subsegment: &Subsegment) {
+        let mut frontier = self.lock();
+        if let Some(precursor_ids) = &subsegment.precursor_ids {
+            frontier.retain(|id| !precursor_ids.contains(id));
+        }
+        frontier.push(subsegment.id.to_string());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<String>> {
+        self.frontier.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
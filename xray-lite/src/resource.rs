@@ -0,0 +1,803 @@
+//! Detection of the AWS resource running your application.
+//!
+//! The detectors in this module fill the `aws` block and `origin` of a
+//! [`Segment`] like the plugins of the official X-Ray SDKs.
+//!
+//! ```no_run
+//! use xray_lite::Segment;
+//!
+//! let mut segment = Segment::begin("my-service");
+//! if let Some(resource) = xray_lite::resource::detect() {
+//!     resource.apply_to(&mut segment);
+//! }
+//! ```
+
+use std::env;
+use std::fs;
+use std::io::{Read as _, Write as _};
+use std::net::{TcpStream, ToSocketAddrs as _};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::segment::{
+    Aws, CloudwatchLogs, Ec2, Ecs, Eks, ElasticBeanstalk, Lambda, Origin, Segment,
+};
+
+/// Default timeout of requests to metadata endpoints.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// AWS resource running your application.
+#[derive(Clone, Debug, Default)]
+pub struct Resource {
+    /// Type of the AWS resource.
+    pub origin: Option<Origin>,
+    /// Information about the AWS resource.
+    pub aws: Aws,
+}
+
+impl Resource {
+    /// Merges another resource into this one.
+    ///
+    /// Fields already set in this resource take precedence.
+    pub fn merge(self, other: Resource) -> Self {
+        Self {
+            origin: self.origin.or(other.origin),
+            aws: Aws {
+                account_id: self.aws.account_id.or(other.aws.account_id),
+                ecs: self.aws.ecs.or(other.aws.ecs),
+                eks: self.aws.eks.or(other.aws.eks),
+                ec2: self.aws.ec2.or(other.aws.ec2),
+                elastic_beanstalk: self.aws.elastic_beanstalk.or(other.aws.elastic_beanstalk),
+                lambda: self.aws.lambda.or(other.aws.lambda),
+                cloudwatch_logs: self.aws.cloudwatch_logs.or(other.aws.cloudwatch_logs),
+                xray: self.aws.xray.or(other.aws.xray),
+            },
+        }
+    }
+
+    /// Updates a segment with the resource.
+    ///
+    /// Fields already set in `segment` are not overwritten.
+    pub fn apply_to(&self, segment: &mut Segment) {
+        if segment.origin.is_none() {
+            segment.origin = self.origin.clone();
+        }
+        let resource = Resource {
+            origin: None,
+            aws: segment.aws.take().unwrap_or_default(),
+        }
+        .merge(self.clone());
+        segment.aws = Some(resource.aws);
+    }
+}
+
+/// Detector of the AWS resource running your application.
+pub trait ResourceDetector {
+    /// Detects the resource.
+    ///
+    /// Returns `None` if your application is not running on the resource
+    /// this detector is responsible for.
+    fn detect(&self) -> Option<Resource>;
+}
+
+/// Detects the resource with the default detectors.
+///
+/// If your application is running on AWS Lambda, only [`LambdaDetector`] is
+/// applied. Otherwise, results of [`BeanstalkDetector`], [`EksDetector`],
+/// [`EcsDetector`] and [`Ec2Detector`] are merged, and the origin is taken
+/// from the most specific one.
+///
+/// Please note that [`Ec2Detector`] may block up to a few seconds if the
+/// instance metadata service is not reachable.
+pub fn detect() -> Option<Resource> {
+    if let Some(resource) = LambdaDetector.detect() {
+        return Some(resource);
+    }
+    let detectors: [&dyn ResourceDetector; 4] = [
+        &BeanstalkDetector::new(),
+        &EksDetector::from_env(),
+        &EcsDetector::from_env(),
+        &Ec2Detector::new(),
+    ];
+    detectors
+        .iter()
+        .filter_map(|detector| detector.detect())
+        .reduce(Resource::merge)
+}
+
+/// Detector of an Amazon ECS container.
+///
+/// Uses the [task metadata endpoint version 4](https://docs.aws.amazon.com/AmazonECS/latest/developerguide/task-metadata-endpoint-v4.html).
+/// The origin is `AWS::ECS::Fargate` for Fargate tasks, otherwise
+/// `AWS::ECS::Container`.
+#[derive(Clone, Debug)]
+pub struct EcsDetector {
+    metadata_uri: Option<String>,
+    hostname: Option<String>,
+    timeout: Duration,
+}
+
+impl EcsDetector {
+    /// Creates a detector from the environment variables.
+    ///
+    /// Reads the following environment variables:
+    /// - `ECS_CONTAINER_METADATA_URI_V4`: task metadata endpoint
+    /// - `HOSTNAME`: hostname of the container
+    pub fn from_env() -> Self {
+        Self {
+            metadata_uri: env::var("ECS_CONTAINER_METADATA_URI_V4").ok(),
+            hostname: env::var("HOSTNAME").ok(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Creates a detector with a given task metadata endpoint.
+    pub fn new(metadata_uri: impl Into<String>) -> Self {
+        Self {
+            metadata_uri: Some(metadata_uri.into()),
+            hostname: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Updates the detector with a given hostname of the container.
+    pub fn with_hostname(self, hostname: impl Into<String>) -> Self {
+        Self {
+            hostname: Some(hostname.into()),
+            ..self
+        }
+    }
+
+    /// Updates the detector with a given timeout of metadata requests.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+}
+
+impl ResourceDetector for EcsDetector {
+    fn detect(&self) -> Option<Resource> {
+        let uri = self.metadata_uri.as_deref()?;
+        let container = http_request("GET", uri, &[], self.timeout)
+            .and_then(|body| serde_json::from_str::<Value>(&body).ok());
+        let task = http_request("GET", &format!("{uri}/task"), &[], self.timeout)
+            .and_then(|body| serde_json::from_str::<Value>(&body).ok());
+        let launch_type = task
+            .as_ref()
+            .and_then(|task| task.get("LaunchType"))
+            .and_then(Value::as_str);
+        let origin = match launch_type {
+            Some(launch_type) if launch_type.eq_ignore_ascii_case("FARGATE") => Origin::EcsFargate,
+            _ => Origin::EcsContainer,
+        };
+        let container_field = |name: &str| string_field(container.as_ref(), name);
+        let task_field = |name: &str| string_field(task.as_ref(), name);
+        let log_group = container
+            .as_ref()
+            .and_then(|container| container.get("LogOptions"))
+            .and_then(|options| options.get("awslogs-group"))
+            .and_then(Value::as_str)
+            .map(|log_group| CloudwatchLogs {
+                log_group: Some(log_group.to_string()),
+                arn: None,
+            });
+        Some(Resource {
+            origin: Some(origin),
+            aws: Aws {
+                ecs: Some(Ecs {
+                    container: self.hostname.clone().or_else(|| container_field("Name")),
+                    container_id: container_field("DockerId"),
+                    container_arn: container_field("ContainerARN"),
+                    cluster_arn: task_field("Cluster"),
+                    task_arn: task_field("TaskARN"),
+                    task_family: task_field("Family"),
+                    launch_type: task_field("LaunchType"),
+                }),
+                cloudwatch_logs: log_group.map(|log_group| vec![log_group]),
+                ..Aws::default()
+            },
+        })
+    }
+}
+
+/// Detector of an Amazon EC2 instance.
+///
+/// Uses the instance metadata service with
+/// [IMDSv2](https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/configuring-instance-metadata-service.html).
+/// The origin is `AWS::EC2::Instance`.
+#[derive(Clone, Debug)]
+pub struct Ec2Detector {
+    endpoint: String,
+    timeout: Duration,
+}
+
+impl Ec2Detector {
+    /// Default endpoint of the instance metadata service.
+    pub const DEFAULT_ENDPOINT: &'static str = "http://169.254.169.254";
+
+    /// Creates a detector with the default endpoint.
+    pub fn new() -> Self {
+        Self::with_endpoint(Self::DEFAULT_ENDPOINT)
+    }
+
+    /// Creates a detector with a given endpoint.
+    pub fn with_endpoint(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Updates the detector with a given timeout of metadata requests.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    fn get_metadata(&self, path: &str, token: Option<&str>) -> Option<String> {
+        let headers: Vec<(&str, &str)> = token
+            .map(|token| vec![("X-aws-ec2-metadata-token", token)])
+            .unwrap_or_default();
+        http_request(
+            "GET",
+            &format!("{}/latest/meta-data/{path}", self.endpoint),
+            &headers,
+            self.timeout,
+        )
+    }
+}
+
+impl Default for Ec2Detector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResourceDetector for Ec2Detector {
+    fn detect(&self) -> Option<Resource> {
+        // falls back to IMDSv1 if no token is available
+        let token = http_request(
+            "PUT",
+            &format!("{}/latest/api/token", self.endpoint),
+            &[("X-aws-ec2-metadata-token-ttl-seconds", "60")],
+            self.timeout,
+        );
+        let instance_id = self.get_metadata("instance-id", token.as_deref())?;
+        let availability_zone = self.get_metadata("placement/availability-zone", token.as_deref());
+        let instance_size = self.get_metadata("instance-type", token.as_deref());
+        let ami_id = self.get_metadata("ami-id", token.as_deref());
+        Some(Resource {
+            origin: Some(Origin::Ec2Instance),
+            aws: Aws {
+                ec2: Some(Ec2 {
+                    instance_id: Some(instance_id),
+                    availability_zone,
+                    instance_size,
+                    ami_id,
+                }),
+                ..Aws::default()
+            },
+        })
+    }
+}
+
+/// Detector of an Elastic Beanstalk environment.
+///
+/// Reads `/var/elasticbeanstalk/xray/environment.conf`.
+/// The origin is `AWS::ElasticBeanstalk::Environment`.
+#[derive(Clone, Debug)]
+pub struct BeanstalkDetector {
+    path: PathBuf,
+}
+
+impl BeanstalkDetector {
+    /// Default path to the environment configuration file.
+    pub const DEFAULT_PATH: &'static str = "/var/elasticbeanstalk/xray/environment.conf";
+
+    /// Creates a detector with the default configuration file.
+    pub fn new() -> Self {
+        Self::with_path(Self::DEFAULT_PATH)
+    }
+
+    /// Creates a detector with a given configuration file.
+    pub fn with_path(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Default for BeanstalkDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResourceDetector for BeanstalkDetector {
+    fn detect(&self) -> Option<Resource> {
+        let conf: Value = serde_json::from_str(&fs::read_to_string(&self.path).ok()?).ok()?;
+        let string_field = |name: &str| conf.get(name).and_then(Value::as_str).map(str::to_string);
+        Some(Resource {
+            origin: Some(Origin::ElasticBeanstalkEnvironment),
+            aws: Aws {
+                elastic_beanstalk: Some(ElasticBeanstalk {
+                    environment_name: string_field("environment_name"),
+                    version_label: string_field("version_label"),
+                    deployment_id: conf
+                        .get("deployment_id")
+                        .and_then(Value::as_u64)
+                        .map(|id| id as usize),
+                }),
+                ..Aws::default()
+            },
+        })
+    }
+}
+
+/// Detector of an Amazon EKS pod.
+///
+/// Your application is considered to be running on Kubernetes if the service
+/// account token is mounted. The container ID is taken from
+/// `/proc/self/cgroup`.
+/// The origin is `AWS::EKS::Container`.
+#[derive(Clone, Debug)]
+pub struct EksDetector {
+    token_path: PathBuf,
+    cgroup_path: PathBuf,
+    hostname: Option<String>,
+    cluster_name: Option<String>,
+}
+
+impl EksDetector {
+    /// Default path to the service account token.
+    pub const DEFAULT_TOKEN_PATH: &'static str =
+        "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+    /// Default path to the cgroup file.
+    pub const DEFAULT_CGROUP_PATH: &'static str = "/proc/self/cgroup";
+
+    /// Creates a detector from the environment.
+    ///
+    /// The pod name is taken from the `HOSTNAME` environment variable.
+    pub fn from_env() -> Self {
+        Self {
+            token_path: Self::DEFAULT_TOKEN_PATH.into(),
+            cgroup_path: Self::DEFAULT_CGROUP_PATH.into(),
+            hostname: env::var("HOSTNAME").ok(),
+            cluster_name: None,
+        }
+    }
+
+    /// Updates the detector with a given path to the service account token.
+    pub fn with_token_path(self, token_path: impl Into<PathBuf>) -> Self {
+        Self {
+            token_path: token_path.into(),
+            ..self
+        }
+    }
+
+    /// Updates the detector with a given path to the cgroup file.
+    pub fn with_cgroup_path(self, cgroup_path: impl Into<PathBuf>) -> Self {
+        Self {
+            cgroup_path: cgroup_path.into(),
+            ..self
+        }
+    }
+
+    /// Updates the detector with a given pod name.
+    pub fn with_hostname(self, hostname: impl Into<String>) -> Self {
+        Self {
+            hostname: Some(hostname.into()),
+            ..self
+        }
+    }
+
+    /// Updates the detector with a given cluster name.
+    ///
+    /// The cluster name is not available from inside a pod without calling
+    /// the Kubernetes API, so you have to supply it; e.g., via an environment
+    /// variable set in your pod spec.
+    pub fn with_cluster_name(self, cluster_name: impl Into<String>) -> Self {
+        Self {
+            cluster_name: Some(cluster_name.into()),
+            ..self
+        }
+    }
+}
+
+impl ResourceDetector for EksDetector {
+    fn detect(&self) -> Option<Resource> {
+        if !self.token_path.exists() {
+            return None;
+        }
+        let container_id = fs::read_to_string(&self.cgroup_path)
+            .ok()
+            .and_then(|cgroup| cgroup.lines().find_map(container_id_in_cgroup));
+        Some(Resource {
+            origin: Some(Origin::EksContainer),
+            aws: Aws {
+                eks: Some(Eks {
+                    pod: self.hostname.clone(),
+                    cluster_name: self.cluster_name.clone(),
+                    container_id,
+                }),
+                ..Aws::default()
+            },
+        })
+    }
+}
+
+/// Detector of an AWS Lambda function.
+///
+/// Your application is considered to be running on AWS Lambda if the
+/// `AWS_LAMBDA_FUNCTION_NAME` environment variable is set. The function
+/// version and log group are taken from `AWS_LAMBDA_FUNCTION_VERSION` and
+/// `AWS_LAMBDA_LOG_GROUP_NAME` respectively.
+/// The origin is `AWS::Lambda::Function`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LambdaDetector;
+
+impl ResourceDetector for LambdaDetector {
+    fn detect(&self) -> Option<Resource> {
+        let function_name = env::var("AWS_LAMBDA_FUNCTION_NAME").ok()?;
+        let log_group =
+            env::var("AWS_LAMBDA_LOG_GROUP_NAME")
+                .ok()
+                .map(|log_group| CloudwatchLogs {
+                    log_group: Some(log_group),
+                    arn: None,
+                });
+        Some(Resource {
+            origin: Some(Origin::LambdaFunction),
+            aws: Aws {
+                lambda: Some(Lambda {
+                    function_name: Some(function_name),
+                    function_version: env::var("AWS_LAMBDA_FUNCTION_VERSION").ok(),
+                    arn: None,
+                }),
+                cloudwatch_logs: log_group.map(|log_group| vec![log_group]),
+                ..Aws::default()
+            },
+        })
+    }
+}
+
+fn string_field(object: Option<&Value>, name: &str) -> Option<String> {
+    object
+        .and_then(|object| object.get(name))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+// extracts a 64-digit hexadecimal container ID from a line of a cgroup file.
+fn container_id_in_cgroup(line: &str) -> Option<String> {
+    line.split(|c: char| !c.is_ascii_hexdigit())
+        .rev()
+        .find(|part| part.len() == 64)
+        .map(str::to_string)
+}
+
+// minimal HTTP/1.0 client for link-local metadata endpoints.
+// returns the body if the response status is 200.
+fn http_request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    timeout: Duration,
+) -> Option<String> {
+    let rest = url.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let addr = if authority.contains(':') {
+        authority.to_socket_addrs()
+    } else {
+        (authority, 80).to_socket_addrs()
+    }
+    .ok()?
+    .next()?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout).ok()?;
+    stream.set_read_timeout(Some(timeout)).ok()?;
+    stream.set_write_timeout(Some(timeout)).ok()?;
+    let mut request = format!("{method} {path} HTTP/1.0\r\nHost: {authority}\r\n");
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str("Content-Length: 0\r\n\r\n");
+    stream.write_all(request.as_bytes()).ok()?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).ok()?;
+    let response = String::from_utf8(response).ok()?;
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    let mut lines = head.lines();
+    let status = lines.next()?.split_whitespace().nth(1)?;
+    if status != "200" {
+        return None;
+    }
+    let chunked = lines.any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding")
+                && value.trim().eq_ignore_ascii_case("chunked")
+        })
+    });
+    if chunked {
+        decode_chunked(body)
+    } else {
+        Some(body.to_string())
+    }
+}
+
+fn decode_chunked(mut body: &str) -> Option<String> {
+    let mut decoded = String::new();
+    loop {
+        let (size, rest) = body.split_once("\r\n")?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.push_str(rest.get(..size)?);
+        body = rest.get(size..)?.strip_prefix("\r\n")?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::io::{BufRead as _, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // serves canned responses keyed by "METHOD /path" on a local port.
+    // requests to other paths receive 404.
+    fn serve(routes: Vec<(&'static str, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let routes: HashMap<_, _> = routes.into_iter().collect();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    headers.push(line.trim().to_string());
+                }
+                let key = request_line
+                    .split_whitespace()
+                    .take(2)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let has_token = headers
+                    .iter()
+                    .any(|h| h.starts_with("X-aws-ec2-metadata-token: token"));
+                let response = match routes.get(key.as_str()) {
+                    Some(body)
+                        if key.starts_with("PUT") || has_token || !key.contains("meta-data") =>
+                    {
+                        format!(
+                            "HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+                            body.len()
+                        )
+                    }
+                    Some(_) => "HTTP/1.0 401 Unauthorized\r\n\r\n".to_string(),
+                    None => "HTTP/1.0 404 Not Found\r\n\r\n".to_string(),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    #[test]
+    fn ecs_detector_should_detect_fargate_task() {
+        let uri = serve(vec![
+            (
+                "GET /v4/abc",
+                r#"{"DockerId":"abc","Name":"app","ContainerARN":"arn:aws:ecs:us-east-1:123456789012:container/c","LogOptions":{"awslogs-group":"/ecs/app"}}"#,
+            ),
+            (
+                "GET /v4/abc/task",
+                r#"{"Cluster":"arn:aws:ecs:us-east-1:123456789012:cluster/default","TaskARN":"arn:aws:ecs:us-east-1:123456789012:task/default/t","Family":"app","LaunchType":"FARGATE"}"#,
+            ),
+        ]);
+        let resource = EcsDetector::new(format!("{uri}/v4/abc")).detect().unwrap();
+        assert_eq!(resource.origin.unwrap(), Origin::EcsFargate);
+        let ecs = resource.aws.ecs.unwrap();
+        assert_eq!(ecs.container.unwrap(), "app");
+        assert_eq!(ecs.container_id.unwrap(), "abc");
+        assert_eq!(
+            ecs.container_arn.unwrap(),
+            "arn:aws:ecs:us-east-1:123456789012:container/c"
+        );
+        assert_eq!(
+            ecs.cluster_arn.unwrap(),
+            "arn:aws:ecs:us-east-1:123456789012:cluster/default"
+        );
+        assert_eq!(
+            ecs.task_arn.unwrap(),
+            "arn:aws:ecs:us-east-1:123456789012:task/default/t"
+        );
+        assert_eq!(ecs.task_family.unwrap(), "app");
+        assert_eq!(ecs.launch_type.unwrap(), "FARGATE");
+        assert_eq!(
+            resource.aws.cloudwatch_logs.unwrap()[0]
+                .log_group
+                .as_deref()
+                .unwrap(),
+            "/ecs/app"
+        );
+    }
+
+    #[test]
+    fn ecs_detector_should_detect_ec2_task() {
+        let uri = serve(vec![
+            ("GET /v4/abc", r#"{"DockerId":"abc","Name":"app"}"#),
+            ("GET /v4/abc/task", r#"{"LaunchType":"EC2"}"#),
+        ]);
+        let resource = EcsDetector::new(format!("{uri}/v4/abc"))
+            .with_hostname("ip-10-0-0-1")
+            .detect()
+            .unwrap();
+        assert_eq!(resource.origin.unwrap(), Origin::EcsContainer);
+        assert_eq!(resource.aws.ecs.unwrap().container.unwrap(), "ip-10-0-0-1");
+    }
+
+    #[test]
+    fn ecs_detector_should_not_detect_without_metadata_uri() {
+        let detector = EcsDetector {
+            metadata_uri: None,
+            hostname: None,
+            timeout: DEFAULT_TIMEOUT,
+        };
+        assert!(detector.detect().is_none());
+    }
+
+    #[test]
+    fn ec2_detector_should_detect_instance_with_imdsv2() {
+        let endpoint = serve(vec![
+            ("PUT /latest/api/token", "token"),
+            ("GET /latest/meta-data/instance-id", "i-0123456789abcdef0"),
+            (
+                "GET /latest/meta-data/placement/availability-zone",
+                "us-east-1a",
+            ),
+            ("GET /latest/meta-data/instance-type", "m5.large"),
+            ("GET /latest/meta-data/ami-id", "ami-0123456789abcdef0"),
+        ]);
+        let resource = Ec2Detector::with_endpoint(endpoint).detect().unwrap();
+        assert_eq!(resource.origin.unwrap(), Origin::Ec2Instance);
+        let ec2 = resource.aws.ec2.unwrap();
+        assert_eq!(ec2.instance_id.unwrap(), "i-0123456789abcdef0");
+        assert_eq!(ec2.availability_zone.unwrap(), "us-east-1a");
+        assert_eq!(ec2.instance_size.unwrap(), "m5.large");
+        assert_eq!(ec2.ami_id.unwrap(), "ami-0123456789abcdef0");
+    }
+
+    #[test]
+    fn ec2_detector_should_not_detect_without_metadata() {
+        let endpoint = serve(vec![]);
+        assert!(Ec2Detector::with_endpoint(endpoint).detect().is_none());
+    }
+
+    #[test]
+    fn beanstalk_detector_should_read_environment_conf() {
+        let path = env::temp_dir().join(format!("xray-lite-beanstalk-{}.conf", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"deployment_id":23,"version_label":"app-5a56-170119_190650-stage-170119_190650","environment_name":"scorekeep"}"#,
+        )
+        .unwrap();
+        let resource = BeanstalkDetector::with_path(&path).detect().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            resource.origin.unwrap(),
+            Origin::ElasticBeanstalkEnvironment
+        );
+        let beanstalk = resource.aws.elastic_beanstalk.unwrap();
+        assert_eq!(beanstalk.environment_name.unwrap(), "scorekeep");
+        assert_eq!(
+            beanstalk.version_label.unwrap(),
+            "app-5a56-170119_190650-stage-170119_190650"
+        );
+        assert_eq!(beanstalk.deployment_id.unwrap(), 23);
+    }
+
+    #[test]
+    fn beanstalk_detector_should_not_detect_missing_conf() {
+        assert!(BeanstalkDetector::with_path("/no/such/environment.conf")
+            .detect()
+            .is_none());
+    }
+
+    #[test]
+    fn eks_detector_should_detect_pod() {
+        let dir = env::temp_dir().join(format!("xray-lite-eks-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("token"), "token").unwrap();
+        fs::write(
+            dir.join("cgroup"),
+            "0::/kubepods.slice/kubepods-besteffort.slice/cri-containerd-0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef.scope\n",
+        )
+        .unwrap();
+        let resource = EksDetector::from_env()
+            .with_token_path(dir.join("token"))
+            .with_cgroup_path(dir.join("cgroup"))
+            .with_hostname("app-5d8f9c-xyz")
+            .with_cluster_name("prod")
+            .detect()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(resource.origin.unwrap(), Origin::EksContainer);
+        let eks = resource.aws.eks.unwrap();
+        assert_eq!(eks.pod.unwrap(), "app-5d8f9c-xyz");
+        assert_eq!(eks.cluster_name.unwrap(), "prod");
+        assert_eq!(
+            eks.container_id.unwrap(),
+            "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"
+        );
+    }
+
+    #[test]
+    fn eks_detector_should_not_detect_without_token() {
+        assert!(EksDetector::from_env()
+            .with_token_path("/no/such/token")
+            .detect()
+            .is_none());
+    }
+
+    #[test]
+    fn merged_resource_should_take_origin_from_first() {
+        let beanstalk = Resource {
+            origin: Some(Origin::ElasticBeanstalkEnvironment),
+            aws: Aws {
+                elastic_beanstalk: Some(ElasticBeanstalk::default()),
+                ..Aws::default()
+            },
+        };
+        let ec2 = Resource {
+            origin: Some(Origin::Ec2Instance),
+            aws: Aws {
+                ec2: Some(Ec2::default()),
+                ..Aws::default()
+            },
+        };
+        let resource = beanstalk.merge(ec2);
+        assert_eq!(
+            resource.origin.unwrap(),
+            Origin::ElasticBeanstalkEnvironment
+        );
+        assert!(resource.aws.elastic_beanstalk.is_some());
+        assert!(resource.aws.ec2.is_some());
+    }
+
+    #[test]
+    fn resource_should_not_overwrite_segment() {
+        let mut segment = Segment::begin("test");
+        segment.origin = Some(Origin::Ec2Instance);
+        Resource {
+            origin: Some(Origin::EcsContainer),
+            aws: Aws {
+                ecs: Some(Ecs::default()),
+                ..Aws::default()
+            },
+        }
+        .apply_to(&mut segment);
+        assert_eq!(segment.origin.unwrap(), Origin::Ec2Instance);
+        assert!(segment.aws.unwrap().ecs.is_some());
+    }
+
+    #[test]
+    fn decode_chunked_should_join_chunks() {
+        assert_eq!(
+            decode_chunked("4\r\ni-01\r\n3\r\n234\r\n0\r\n\r\n").unwrap(),
+            "i-01234"
+        );
+    }
+}
@@ -1,14 +1,15 @@
-use crate::{Seconds, SegmentId, TraceId};
-use serde::{Deserialize, Serialize};
+use crate::{Header, Seconds, SegmentId, TraceId};
+use serde::{de, ser, Deserialize, Serialize};
 use serde_json::Value;
-use std::{collections::HashMap, ops::Not};
+use std::{collections::HashMap, convert::Infallible, fmt, ops::Not, str::FromStr};
 
 // https://docs.aws.amazon.com/xray/latest/devguide/xray-api-sendingdata.html
 // https://docs.aws.amazon.com/xray/latest/devguide/xray-api-segmentdocuments.html
 
 /// Description of an internal application operation
 /// which may be an extension of an external operation
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Segment {
     /// A unique identifier that connects all segments and subsegments originating from a single client request.
     pub(crate) trace_id: TraceId,
@@ -42,14 +43,14 @@ pub struct Segment {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cause: Option<Cause>,
     /// The type of AWS resource running your application.
-    /// todo: convert to enum (see aws docs for values)
+    ///
     /// When multiple values are applicable to your application, use the one that is most specific. For example, a Multicontainer Docker Elastic Beanstalk environment runs your application on an Amazon ECS container, which in turn runs on an Amazon EC2 instance. In this case you would set the origin to AWS::ElasticBeanstalk::Environment as the environment is the parent of the other two resources.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub origin: Option<String>,
+    pub origin: Option<Origin>,
     /// A string that identifies the user who sent the request.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
-    ///
+    /// The ARN of the AWS resource running your application.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub resource_arn: Option<String>,
     /// http objects with information about the original HTTP request.
@@ -67,10 +68,92 @@ pub struct Segment {
     /// An object with information about your application.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub service: Option<Service>,
+    /// array of subsegment objects.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subsegments: Vec<Subsegment>,
+}
+
+/// The type of AWS resource running your application.
+///
+/// Serialized as the resource type string; e.g., `AWS::EC2::Instance`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// `AWS::EC2::Instance`: An Amazon EC2 instance.
+    Ec2Instance,
+    /// `AWS::ECS::Container`: An Amazon ECS container.
+    EcsContainer,
+    /// `AWS::ECS::Fargate`: An Amazon ECS container on AWS Fargate.
+    EcsFargate,
+    /// `AWS::EKS::Container`: An Amazon EKS container.
+    EksContainer,
+    /// `AWS::ElasticBeanstalk::Environment`: An Elastic Beanstalk environment.
+    ElasticBeanstalkEnvironment,
+    /// `AWS::Lambda::Function`: An AWS Lambda function.
+    LambdaFunction,
+    /// Any other resource type.
+    Other(String),
+}
+
+impl Origin {
+    /// Returns the resource type string.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Origin::Ec2Instance => "AWS::EC2::Instance",
+            Origin::EcsContainer => "AWS::ECS::Container",
+            Origin::EcsFargate => "AWS::ECS::Fargate",
+            Origin::EksContainer => "AWS::EKS::Container",
+            Origin::ElasticBeanstalkEnvironment => "AWS::ElasticBeanstalk::Environment",
+            Origin::LambdaFunction => "AWS::Lambda::Function",
+            Origin::Other(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Origin {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "AWS::EC2::Instance" => Origin::Ec2Instance,
+            "AWS::ECS::Container" => Origin::EcsContainer,
+            "AWS::ECS::Fargate" => Origin::EcsFargate,
+            "AWS::EKS::Container" => Origin::EksContainer,
+            "AWS::ElasticBeanstalk::Environment" => Origin::ElasticBeanstalkEnvironment,
+            "AWS::Lambda::Function" => Origin::LambdaFunction,
+            _ => Origin::Other(s.to_string()),
+        })
+    }
+}
+
+impl ser::Serialize for Origin {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Origin {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Ok(value
+            .parse()
+            .unwrap_or_else(|never: Infallible| match never {}))
+    }
 }
 
 ///  An object with information about your application.
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Service {
     /// A string that identifies the version of your application that served the request.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -78,7 +161,7 @@ pub struct Service {
 }
 
 /// Context information about the AWS environment this segment was run in
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Aws {
     ///  If your application sends segments to a different AWS account, record the ID of the account running your application.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -86,34 +169,107 @@ pub struct Aws {
     ///  Information about an Amazon ECS container.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ecs: Option<Ecs>,
+    ///  Information about an Amazon EKS pod.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eks: Option<Eks>,
     ///  Information about an EC2 instance.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ec2: Option<Ec2>,
     /// Information about an Elastic Beanstalk environment. You can find this information in a file named /var/elasticbeanstalk/xray/environment.conf on the latest Elastic Beanstalk platforms.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub elastic_beanstalk: Option<ElasticBeanstalk>,
+    /// Information about an AWS Lambda function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lambda: Option<Lambda>,
+    /// CloudWatch Logs log groups your application writes to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cloudwatch_logs: Option<Vec<CloudwatchLogs>>,
     /// Metadata about the type and version of instrumentation used.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub xray: Option<XRay>,
 }
 
 /// Metadata about the type and version of instrumentation used.
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct XRay {
     /// The version of SDK or agent being used.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub sdk_version: Option<String>,
+    /// The name of SDK or agent being used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sdk: Option<String>,
+    /// boolean indicating whether the application was instrumented automatically; e.g., by an agent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_instrumentation: Option<bool>,
+}
+
+/// Reference to a CloudWatch Logs log group.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CloudwatchLogs {
+    /// The name of the log group.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_group: Option<String>,
+    /// The ARN of the log group.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arn: Option<String>,
+}
+
+/// Information about an AWS Lambda function.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Lambda {
+    /// The name of the function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_name: Option<String>,
+    /// The version of the function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_version: Option<String>,
+    /// The ARN of the function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arn: Option<String>,
 }
 
 /// Information about an Amazon ECS container.
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Ecs {
-    /// The container ID of the container running your application.
+    /// The hostname of the container running your application.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub container: Option<String>,
+    /// The full container ID of the container running your application.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    /// The ARN of the container.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_arn: Option<String>,
+    /// The ARN of the ECS cluster.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster_arn: Option<String>,
+    /// The ARN of the ECS task.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_arn: Option<String>,
+    /// The family of the ECS task definition.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_family: Option<String>,
+    /// The launch type of the ECS task; e.g., `FARGATE` or `EC2`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub launch_type: Option<String>,
+}
+
+/// Information about an Amazon EKS pod.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Eks {
+    /// The hostname of your EKS pod.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod: Option<String>,
+    /// The name of the EKS cluster.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster_name: Option<String>,
+    /// The full container ID of the container running your application.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
 }
 
 /// Information about an EC2 instance.
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Ec2 {
     /// The instance ID of the EC2 instance.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -121,10 +277,16 @@ pub struct Ec2 {
     /// The Availability Zone in which the instance is running.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub availability_zone: Option<String>,
+    /// The type of the EC2 instance; e.g., `m5.large`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance_size: Option<String>,
+    /// The ID of the Amazon Machine Image the instance was launched from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ami_id: Option<String>,
 }
 
 /// Information about an Elastic Beanstalk environment. You can find this information in a file named /var/elasticbeanstalk/xray/environment.conf on the latest Elastic Beanstalk platforms.
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ElasticBeanstalk {
     /// The name of the environment.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -145,26 +307,85 @@ impl Default for Annotation {
 
 /// A value type which may be used for
 /// filter querying
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Annotation {
     /// A string value
     String(String),
+    /// A signed integer value
+    ///
+    /// Precedes `Number` so that deserialized integers end up here unless
+    /// they exceed the range of `i64`.
+    Integer(i64),
     /// A numberic value
     Number(usize),
     /// A boolean value
     Bool(bool),
+    /// A floating-point value
+    Float(f64),
+}
+
+impl From<String> for Annotation {
+    fn from(value: String) -> Self {
+        Annotation::String(value)
+    }
+}
+
+impl From<&str> for Annotation {
+    fn from(value: &str) -> Self {
+        Annotation::String(value.into())
+    }
+}
+
+impl From<bool> for Annotation {
+    fn from(value: bool) -> Self {
+        Annotation::Bool(value)
+    }
+}
+
+/// An integer beyond the range of `i64` falls back to `Number`.
+impl From<usize> for Annotation {
+    fn from(value: usize) -> Self {
+        i64::try_from(value).map_or(Annotation::Number(value), Annotation::Integer)
+    }
+}
+
+impl From<i32> for Annotation {
+    fn from(value: i32) -> Self {
+        Annotation::Integer(value.into())
+    }
+}
+
+impl From<i64> for Annotation {
+    fn from(value: i64) -> Self {
+        Annotation::Integer(value)
+    }
+}
+
+impl From<u32> for Annotation {
+    fn from(value: u32) -> Self {
+        Annotation::Integer(value.into())
+    }
+}
+
+impl From<f64> for Annotation {
+    fn from(value: f64) -> Self {
+        Annotation::Float(value)
+    }
 }
 
 /// Detailed representation of an exception
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Exception {
     /// A 64-bit identifier for the exception, unique among segments in the same trace, in 16 hexadecimal digits.
     pub id: String,
     /// The exception message.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub messages: Option<String>,
+    pub message: Option<String>,
     /// The exception type.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    /// boolean indicating that the exception was caused by an error returned by a downstream service.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub remote: Option<bool>,
     /// integer indicating the number of stack frames that are omitted from the stack.
@@ -177,25 +398,26 @@ pub struct Exception {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cause: Option<String>,
     /// array of stackFrame objects.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub stack: Vec<StackFrame>,
 }
 
 /// A summary of a single operation within a stack trace
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct StackFrame {
     /// The relative path to the file.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub path: Option<String>,
     /// The line in the file.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub line: Option<String>,
+    pub line: Option<u32>,
     /// The function or method name.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
 }
 
 /// Represents the cause of an errror
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Cause {
     ///  a 16 character exception ID
@@ -229,6 +451,21 @@ impl Segment {
         }
     }
 
+    /// Begins a new named segment continuing the trace of a trace header
+    ///
+    /// The segment gets the trace ID and the parent ID of the header; e.g., of
+    /// a message received from a traced producer.
+    pub fn linked<N>(name: N, header: &Header) -> Self
+    where
+        N: Into<String>,
+    {
+        Segment {
+            trace_id: header.trace_id.clone(),
+            parent_id: header.parent_id.clone(),
+            ..Segment::begin(name)
+        }
+    }
+
     /// End the segment by assigning its end_time
     pub fn end(&mut self) -> &mut Self {
         self.end_time = Some(Seconds::now());
@@ -238,7 +475,7 @@ impl Segment {
 }
 
 /// Describes an http request/response cycle
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct Http {
     /// Information about a request
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -249,7 +486,7 @@ pub struct Http {
 }
 
 ///  Information about a request.
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct Request {
     /// The request method. For example, GET.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -265,14 +502,14 @@ pub struct Request {
     pub user_agent: Option<String>,
     /// (segments only) boolean indicating that the client_ip was read from an X-Forwarded-For header and is not reliable as it could have been forged.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub x_forwarded_for: Option<String>,
+    pub x_forwarded_for: Option<bool>,
     /// (subsegments only) boolean indicating that the downstream call is to another traced service. If this field is set to true, X-Ray considers the trace to be broken until the downstream service uploads a segment with a parent_id that matches the id of the subsegment that contains this block.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub traced: Option<bool>,
 }
 
 ///  Information about a response.
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct Response {
     /// number indicating the HTTP status of the response.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -311,7 +548,8 @@ impl Subsegment {
 }
 
 /// Record information about the AWS services and resources that your application accesses. X-Ray uses this information to create inferred segments that represent the downstream services in your service map.
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Subsegment {
     /// The logical name of the subsegment. For downstream calls, name the subsegment after the resource or service called. For custom subsegments, name the subsegment after the code that it instruments (e.g., a function name).
     pub(crate) name: String,
@@ -338,12 +576,12 @@ pub struct Subsegment {
     #[serde(skip_serializing_if = "Not::not")]
     pub error: bool,
     ///  boolean indicating that a request was throttled (response status code was 429 Too Many Requests).
-    #[serde(skip_serializing_if = "Not::not")]
+    #[serde(rename = "throttle", skip_serializing_if = "Not::not")]
     pub throttled: bool,
     /// aws for AWS SDK calls; remote for other downstream calls.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub namespace: Option<String>,
-    ///
+    /// boolean indicating that the downstream call is to another traced service.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub traced: Option<bool>,
     /// array of subsegment IDs that identifies subsegments with the same parent that completed prior to this subsegment.
@@ -359,7 +597,7 @@ pub struct Subsegment {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<HashMap<String, Value>>,
     /// subsegment. Required only if sending a subsegment separately.
-    #[serde(rename = "type")]
+    #[serde(rename = "type", skip_serializing_if = "String::is_empty")]
     pub type_: String,
     /// array of subsegment objects.
     #[serde(skip_serializing_if = "Vec::is_empty")]
@@ -376,7 +614,7 @@ pub struct Subsegment {
 }
 
 /// Information about an AWS operation
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct AwsOperation {
     /// The name of the API action invoked against an AWS service or resource.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -396,10 +634,16 @@ pub struct AwsOperation {
     /// For operations on a DynamoDB table, the name of the table.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub table_name: Option<String>,
+    /// The number of times the request was retried.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Names of the resources the operation accessed; e.g., bucket names or table names.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_names: Option<Vec<String>>,
 }
 
 /// Information about a SQL operation
-#[derive(Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Sql {
     /// For SQL Server or other database connections that don't use URL connection strings, record the connection string, excluding passwords.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -429,7 +673,22 @@ pub struct Sql {
 
 #[cfg(test)]
 mod tests {
-    use super::{Seconds, Segment, SegmentId, Subsegment, TraceId};
+    use super::{
+        Annotation, AwsOperation, Cause, Exception, Origin, Seconds, Segment, SegmentId,
+        Subsegment, TraceId,
+    };
+
+    #[test]
+    fn linked_segments_continue_trace_of_header() {
+        let header: crate::Header =
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1"
+                .parse()
+                .unwrap();
+        let segment = Segment::linked("consumer", &header);
+        assert_eq!(segment.name, "consumer");
+        assert_eq!(segment.trace_id, header.trace_id);
+        assert_eq!(segment.parent_id, header.parent_id);
+    }
 
     #[test]
     fn segments_begin_with_names_with_a_max_len() {
@@ -475,4 +734,146 @@ mod tests {
             .expect("failed to serialize")
         )
     }
+
+    #[test]
+    fn origins_serialize_as_resource_types() {
+        assert_eq!(
+            serde_json::to_string(&Origin::EcsFargate).unwrap(),
+            r#""AWS::ECS::Fargate""#
+        );
+        assert_eq!(
+            serde_json::to_string(&Origin::Other("AWS::AppRunner::Service".into())).unwrap(),
+            r#""AWS::AppRunner::Service""#
+        );
+    }
+
+    #[test]
+    fn origins_deserialize_from_resource_types() {
+        assert_eq!(
+            serde_json::from_str::<Origin>(r#""AWS::ElasticBeanstalk::Environment""#).unwrap(),
+            Origin::ElasticBeanstalkEnvironment
+        );
+        assert_eq!(
+            serde_json::from_str::<Origin>(r#""AWS::AppRunner::Service""#).unwrap(),
+            Origin::Other("AWS::AppRunner::Service".into())
+        );
+    }
+
+    #[test]
+    fn annotations_serialize_signed_and_floating_point_numbers() {
+        assert_eq!(
+            serde_json::to_string(&vec![
+                Annotation::from(-1),
+                Annotation::from(0.5),
+                Annotation::from(42usize),
+                Annotation::from("x"),
+                Annotation::from(true),
+            ])
+            .unwrap(),
+            r#"[-1,0.5,42,"x",true]"#
+        );
+    }
+
+    #[test]
+    fn annotations_from_integers_should_be_integers() {
+        assert_eq!(Annotation::from(42usize), Annotation::Integer(42));
+        assert_eq!(Annotation::from(42u32), Annotation::Integer(42));
+        assert_eq!(Annotation::from(42i32), Annotation::Integer(42));
+        assert_eq!(Annotation::from(42i64), Annotation::Integer(42));
+    }
+
+    #[test]
+    fn annotations_deserialize_numbers() {
+        assert_eq!(
+            serde_json::from_str::<Vec<Annotation>>(r#"[42,-1,18446744073709551615,0.5]"#).unwrap(),
+            vec![
+                Annotation::Integer(42),
+                Annotation::Integer(-1),
+                Annotation::Number(usize::MAX),
+                Annotation::Float(0.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn annotations_round_trip_integers() {
+        for annotation in [Annotation::from(42usize), Annotation::from(usize::MAX)] {
+            let json = serde_json::to_string(&annotation).unwrap();
+            assert_eq!(
+                serde_json::from_str::<Annotation>(&json).unwrap(),
+                annotation
+            );
+        }
+    }
+
+    #[test]
+    fn subsegments_serialize_retries_and_throttle() {
+        let subsegment = Subsegment {
+            name: "DynamoDB".into(),
+            id: SegmentId::Rendered("70de5b6f19ff9a0a".into()),
+            start_time: Seconds(1_478_293_361.271),
+            end_time: Some(Seconds(1_478_293_361.449)),
+            throttled: true,
+            aws: Some(AwsOperation {
+                operation: Some("GetItem".into()),
+                retries: Some(2),
+                ..AwsOperation::default()
+            }),
+            ..Subsegment::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&subsegment).unwrap(),
+            r#"{"name":"DynamoDB","id":"70de5b6f19ff9a0a","start_time":1478293361.271,"end_time":1478293361.449,"throttle":true,"aws":{"operation":"GetItem","retries":2}}"#
+        );
+    }
+
+    #[test]
+    fn subsegments_serialize_type_only_if_set() {
+        let mut subsegment = Subsegment {
+            name: "S3".into(),
+            id: SegmentId::Rendered("53995c3f42cd8ad8".into()),
+            start_time: Seconds(1_478_293_361.3),
+            ..Subsegment::default()
+        };
+        assert!(serde_json::to_value(&subsegment)
+            .unwrap()
+            .get("type")
+            .is_none());
+        subsegment.type_ = "subsegment".into();
+        assert_eq!(
+            serde_json::to_value(&subsegment).unwrap()["type"],
+            "subsegment"
+        );
+    }
+
+    #[test]
+    fn causes_serialize_exception_message_and_type() {
+        let cause = Cause::Description {
+            working_directory: "/var/task".into(),
+            paths: vec![],
+            exceptions: vec![Exception {
+                id: "0123456789abcdef".into(),
+                message: Some("not found".into()),
+                type_: Some("NotFound".into()),
+                ..Exception::default()
+            }],
+        };
+        assert_eq!(
+            serde_json::to_string(&cause).unwrap(),
+            r#"{"working_directory":"/var/task","paths":[],"exceptions":[{"id":"0123456789abcdef","message":"not found","type":"NotFound"}]}"#
+        );
+    }
+
+    #[test]
+    fn segments_deserialize() {
+        let segment: Segment = serde_json::from_str(
+            r#"{"trace_id":"1-581cf771-a006649127e371903a2de979","id":"70de5b6f19ff9a0a","name":"Scorekeep","start_time":1478293361,"end_time":1478293361.449,"origin":"AWS::EC2::Instance","subsegments":[{"name":"S3","id":"53995c3f42cd8ad8","start_time":1478293361.3,"throttle":true}]}"#,
+        )
+        .unwrap();
+        assert_eq!(segment.name, "Scorekeep");
+        assert_eq!(segment.start_time, Seconds(1_478_293_361.0));
+        assert_eq!(segment.origin, Some(Origin::Ec2Instance));
+        assert_eq!(segment.subsegments.len(), 1);
+        assert!(segment.subsegments[0].throttled);
+    }
 }
@@ -1,7 +1,10 @@
-use crate::hexbytes::Bytes;
+use crate::{
+    error::Error,
+    hexbytes::{is_hex, Bytes},
+};
 use rand::RngCore;
 use serde::{de, ser, Serializer};
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 /// Unique identifier of an operation within a trace
 #[derive(Debug, PartialEq, Clone)]
@@ -30,6 +33,19 @@ impl fmt::Display for SegmentId {
     }
 }
 
+impl FromStr for SegmentId {
+    type Err = Error;
+
+    /// Parses a segment ID of 16 hex digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if is_hex(s, 16) {
+            Ok(SegmentId::Rendered(s.into()))
+        } else {
+            Err(Error::InvalidSegmentId(s.into()))
+        }
+    }
+}
+
 impl Default for SegmentId {
     fn default() -> Self {
         SegmentId::new()
@@ -1,18 +1,91 @@
 //! Subsegment session management.
 
+use std::fmt::Display;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
 use crate::client::Client;
+use crate::context::{Parent, SubsegmentContext};
+use crate::deferred::Deferred;
+use crate::embedded::{Embedded, Embedding};
+use crate::error::Result;
 use crate::header::Header;
+use crate::hook::report_error;
 use crate::namespace::Namespace;
-use crate::segment::Subsegment;
+use crate::precursor::Precursors;
+use crate::segment::{Annotation, Cause, Exception, Subsegment};
+use crate::segment_id::SegmentId;
+
+/// When the in-progress document of a subsegment is sent.
+///
+/// An in-progress document lets X-Ray show a subsegment before it ends, at the
+/// cost of an extra packet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InProgressPolicy {
+    /// Sent when the subsegment is entered.
+    #[default]
+    Always,
+    /// Sent only for a subsegment still running after a given duration.
+    ///
+    /// The document is sent from a background thread shared by all the
+    /// sessions, unless the subsegment has ended by then.
+    After(Duration),
+    /// Never sent.
+    Never,
+}
+
+/// In-progress policy of a context with the means to carry it out.
+#[derive(Clone, Default)]
+pub(crate) struct InProgress {
+    policy: InProgressPolicy,
+    // schedules the in-progress document of a subsegment; only for `After`
+    #[allow(clippy::type_complexity)]
+    schedule: Option<Arc<dyn Fn(&Subsegment) -> Deferred + Send + Sync>>,
+}
+
+impl InProgress {
+    /// Prepares a given policy for subsegments sent through a given client.
+    pub(crate) fn new<C>(policy: InProgressPolicy, client: &C) -> Self
+    where
+        C: Client + 'static,
+    {
+        let schedule = match policy {
+            InProgressPolicy::After(delay) => {
+                let client = client.clone();
+                let schedule = move |subsegment: &Subsegment| {
+                    let client = client.clone();
+                    let subsegment = subsegment.clone();
+                    Deferred::schedule(delay, move || {
+                        if let Err(e) = client.send(&subsegment) {
+                            report_error("failed to begin subsegment", &e);
+                        }
+                    })
+                };
+                Some(Arc::new(schedule) as Arc<dyn Fn(&Subsegment) -> Deferred + Send + Sync>)
+            }
+            InProgressPolicy::Always | InProgressPolicy::Never => None,
+        };
+        Self { policy, schedule }
+    }
+}
+
+impl std::fmt::Debug for InProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.policy.fmt(f)
+    }
+}
 
 /// Subsegment session.
 #[derive(Debug)]
+// `subsegment` stays unboxed so that it can be matched by value
+#[allow(clippy::large_enum_variant)]
 pub enum SubsegmentSession<C, N>
 where
     C: Client,
     N: Namespace + Send + Sync,
 {
     /// Entered subsegment.
+    #[non_exhaustive]
     Entered {
         /// X-Ray client.
         client: C,
@@ -22,8 +95,10 @@ where
         subsegment: Subsegment,
         /// Namespace.
         namespace: N,
+        /// Where the subsegment and its children are recorded.
+        scope: Box<SessionScope>,
     },
-    /// Failed subsegment.
+    /// Non-operational session; e.g., entered in a no-op context.
     Failed,
 }
 
@@ -32,21 +107,61 @@ where
     C: Client,
     N: Namespace + Send + Sync,
 {
-    pub(crate) fn new(client: C, header: &Header, namespace: N, name_prefix: &str) -> Self {
+    pub(crate) fn new(
+        client: C,
+        parent: &Parent,
+        namespace: N,
+        name_prefix: &str,
+        in_progress: InProgress,
+    ) -> Self {
+        let header = &parent.header;
         let mut subsegment = Subsegment::begin(
             header.trace_id.clone(),
             header.parent_id.clone(),
             namespace.name(name_prefix),
         );
         namespace.update_subsegment(&mut subsegment);
-        match client.send(&subsegment) {
-            Ok(_) => Self::Entered {
-                client,
-                header: header.with_parent_id(subsegment.id.clone()),
-                subsegment,
-                namespace,
-            },
-            Err(_) => Self::Failed,
+        if let Some(precursors) = &parent.precursors {
+            precursors.enter(&mut subsegment);
+        }
+        let (embedded_parent, children) = match &parent.embedding {
+            Embedding::Off => (None, None),
+            Embedding::Root {
+                streaming_threshold,
+            } => (None, Some(Embedded::root(*streaming_threshold))),
+            Embedding::Child(parent) => (Some(parent.clone()), Some(parent.child())),
+        };
+        // an embedded subsegment is not reported until it ends
+        let mut pending_in_progress = None;
+        if embedded_parent.is_none() {
+            match in_progress.policy {
+                InProgressPolicy::Always => {
+                    if let Err(e) = client.send(&subsegment) {
+                        report_error("failed to begin subsegment", &e);
+                    }
+                }
+                InProgressPolicy::After(_) => {
+                    pending_in_progress = in_progress.schedule.as_ref().map(|f| f(&subsegment));
+                }
+                InProgressPolicy::Never => {}
+            }
+        }
+        Self::Entered {
+            client,
+            header: header.with_parent_id(subsegment.id.clone()),
+            subsegment,
+            namespace,
+            scope: Box::new(SessionScope {
+                name_prefix: name_prefix.to_string(),
+                parent: embedded_parent,
+                children,
+                in_progress,
+                pending_in_progress,
+                ended: false,
+                depth: parent.depth,
+                siblings: parent.precursors.clone(),
+                children_precursors: parent.precursors.as_ref().map(|_| Precursors::default()),
+            }),
         }
     }
 
@@ -62,6 +177,55 @@ where
         }
     }
 
+    /// Sends the pending in-progress document now.
+    ///
+    /// If the session was entered with [`InProgressPolicy::After`], the
+    /// in-progress document is sent without waiting for the duration; e.g.,
+    /// when you know that the work is going to take long. Does nothing if the
+    /// document has already been sent, or the policy is otherwise. A failure
+    /// to send the in-progress document does not prevent the completed
+    /// subsegment from being sent.
+    pub fn heartbeat(&mut self) {
+        if let Self::Entered { scope, .. } = self {
+            if let Some(pending) = &scope.pending_in_progress {
+                pending.run_now();
+            }
+        }
+    }
+
+    /// Returns a context to enter children of the subsegment.
+    ///
+    /// If the session was entered in a context with embedded subsegments,
+    /// children entered in the returned context are embedded into the
+    /// subsegment.
+    pub fn child_context(&self) -> Option<SubsegmentContext<C>> {
+        match self {
+            Self::Entered { client, scope, .. } => Some(SubsegmentContext::child(
+                client.clone(),
+                scope.name_prefix.clone(),
+                scope.in_progress.clone(),
+                self.as_parent()?,
+            )),
+            Self::Failed => None,
+        }
+    }
+
+    /// Returns where children of the subsegment are entered.
+    pub(crate) fn as_parent(&self) -> Option<Parent> {
+        match self {
+            Self::Entered { header, scope, .. } => Some(Parent {
+                header: header.clone(),
+                embedding: scope
+                    .children
+                    .clone()
+                    .map_or(Embedding::Off, Embedding::Child),
+                depth: scope.depth + 1,
+                precursors: scope.children_precursors.clone(),
+            }),
+            Self::Failed => None,
+        }
+    }
+
     /// Returns the namespace as a mutable reference.
     pub fn namespace_mut(&mut self) -> Option<&mut N> {
         match self {
@@ -69,28 +233,710 @@ where
             Self::Failed => None,
         }
     }
-}
 
-impl<C, N> Drop for SubsegmentSession<C, N>
-where
-    C: Client,
-    N: Namespace + Send + Sync,
-{
-    fn drop(&mut self) {
+    /// Returns the ID of the subsegment.
+    ///
+    /// Pass it to [`SubsegmentSession::precursor_id`] of a later sibling to
+    /// link them; e.g., to make a subsegment follow parallel ones.
+    pub fn id(&self) -> Option<SegmentId> {
+        match self {
+            Self::Entered { subsegment, .. } => Some(subsegment.id.clone()),
+            Self::Failed => None,
+        }
+    }
+
+    /// Adds the ID of a sibling subsegment that completed before this one.
+    pub fn precursor_id(&mut self, precursor_id: SegmentId) {
+        if let Self::Entered { subsegment, .. } = self {
+            let precursor_id = precursor_id.to_string();
+            let precursor_ids = subsegment.precursor_ids.get_or_insert_with(Vec::new);
+            if !precursor_ids.contains(&precursor_id) {
+                precursor_ids.push(precursor_id);
+            }
+        }
+    }
+
+    /// Sets an annotation of the subsegment.
+    pub fn annotation(&mut self, key: impl Into<String>, value: impl Into<Annotation>) {
+        if let Self::Entered { subsegment, .. } = self {
+            subsegment
+                .annotations
+                .get_or_insert_with(Default::default)
+                .insert(key.into(), value.into());
+        }
+    }
+
+    /// Detaches the session so that it can be ended on another thread.
+    ///
+    /// ```
+    /// use xray_lite::{Context as _, CustomNamespace, DaemonClient, SubsegmentContext};
+    ///
+    /// # std::env::set_var("_X_AMZN_TRACE_ID", "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1");
+    /// let client = DaemonClient::new(([127, 0, 0, 1], 2000).into()).unwrap();
+    /// let context = SubsegmentContext::from_lambda_env(client).unwrap();
+    /// let session = context
+    ///     .enter_subsegment(CustomNamespace::new("callback"))
+    ///     .detach();
+    /// let handle = session.clone();
+    /// std::thread::spawn(move || {
+    ///     handle.annotation("status", "done");
+    ///     let _ = handle.end();
+    /// })
+    /// .join()
+    /// .unwrap();
+    /// assert!(session.is_ended());
+    /// ```
+    pub fn detach(self) -> DetachedSession<C, N> {
+        DetachedSession {
+            session: Arc::new(Mutex::new(Some(self))),
+        }
+    }
+
+    /// Ends the subsegment and sends it.
+    ///
+    /// Unlike dropping the session, this returns the result of sending the
+    /// subsegment. An embedded subsegment is not sent until its parent ends,
+    /// so this succeeds unless it is streamed.
+    pub fn end(mut self) -> Result<()> {
+        self.finish()
+    }
+
+    /// Ends the subsegment with the outcome of the operation it traces.
+    ///
+    /// The outcome is recorded in the subsegment before it ends; e.g., an
+    /// `Err` marks the subsegment as a fault and records the error as an
+    /// exception in the cause. If the cause refers to an exception by ID, it
+    /// is replaced with the recorded exception, which refers to the former as
+    /// its cause. Otherwise, this is the same as [`SubsegmentSession::end`].
+    ///
+    /// ```
+    /// use xray_lite::{Context as _, CustomNamespace, DaemonClient, SubsegmentContext};
+    ///
+    /// # std::env::set_var("_X_AMZN_TRACE_ID", "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1");
+    /// let client = DaemonClient::new(([127, 0, 0, 1], 2000).into()).unwrap();
+    /// let context = SubsegmentContext::from_lambda_env(client).unwrap();
+    /// let session = context.enter_subsegment(CustomNamespace::new("parse"));
+    /// let result = "forty-two".parse::<u32>();
+    /// if let Err(e) = session.end_with(&result) {
+    ///     eprintln!("failed to send subsegment: {e}");
+    /// }
+    /// ```
+    pub fn end_with<O>(mut self, outcome: &O) -> Result<()>
+    where
+        O: Outcome + ?Sized,
+    {
+        if let Self::Entered { subsegment, .. } = &mut self {
+            outcome.record(subsegment);
+        }
+        self.finish()
+    }
+
+    // ends and sends the subsegment unless it has already ended.
+    fn finish(&mut self) -> Result<()> {
         match self {
             Self::Entered {
                 client,
                 subsegment,
                 namespace,
+                scope,
                 ..
             } => {
+                if scope.ended {
+                    return Ok(());
+                }
+                scope.ended = true;
+                // the in-progress document must not follow the completed one
+                if let Some(pending) = scope.pending_in_progress.take() {
+                    pending.cancel();
+                }
                 subsegment.end();
                 namespace.update_subsegment(subsegment);
-                let _ = client
-                    .send(subsegment)
-                    .map_err(|e| eprintln!("failed to end subsegment: {e}"));
+                if let Some(siblings) = &scope.siblings {
+                    siblings.complete(subsegment);
+                }
+                if let Some(children) = &scope.children {
+                    subsegment.subsegments.extend(children.close());
+                }
+                if let Some(parent) = &scope.parent {
+                    match parent.embed(std::mem::take(subsegment)) {
+                        Some(streamed) => *subsegment = streamed,
+                        None => return Ok(()),
+                    }
+                }
+                client.send(subsegment)
+            }
+            Self::Failed => Ok(()),
+        }
+    }
+}
+
+/// Subsegment session shared across threads.
+///
+/// Created by [`SubsegmentSession::detach`]. The subsegment is ended exactly
+/// once; by the first call to [`DetachedSession::end`] or
+/// [`DetachedSession::end_with`], or when the last handle is dropped.
+#[derive(Debug)]
+pub struct DetachedSession<C, N>
+where
+    C: Client,
+    N: Namespace + Send + Sync,
+{
+    // `None` once ended
+    session: Arc<Mutex<Option<SubsegmentSession<C, N>>>>,
+}
+
+impl<C, N> DetachedSession<C, N>
+where
+    C: Client,
+    N: Namespace + Send + Sync,
+{
+    /// Returns the `x-amzn-trace-id` header value.
+    ///
+    /// `None` if the session is non-operational or has ended.
+    pub fn x_amzn_trace_id(&self) -> Option<String> {
+        self.with_session(|session| session.x_amzn_trace_id())
+            .flatten()
+    }
+
+    /// Returns a context to enter children of the subsegment.
+    ///
+    /// `None` if the session is non-operational or has ended.
+    pub fn child_context(&self) -> Option<SubsegmentContext<C>> {
+        self.with_session(|session| session.child_context())
+            .flatten()
+    }
+
+    /// Sends the in-progress document if it is due.
+    ///
+    /// See [`SubsegmentSession::heartbeat`].
+    pub fn heartbeat(&self) {
+        self.with_session(SubsegmentSession::heartbeat);
+    }
+
+    /// Returns the ID of the subsegment.
+    ///
+    /// `None` if the session is non-operational or has ended.
+    pub fn id(&self) -> Option<SegmentId> {
+        self.with_session(|session| session.id()).flatten()
+    }
+
+    /// Adds the ID of a sibling subsegment unless the subsegment has ended.
+    ///
+    /// See [`SubsegmentSession::precursor_id`].
+    pub fn precursor_id(&self, precursor_id: SegmentId) {
+        self.with_session(|session| session.precursor_id(precursor_id));
+    }
+
+    /// Sets an annotation of the subsegment unless it has ended.
+    pub fn annotation(&self, key: impl Into<String>, value: impl Into<Annotation>) {
+        self.with_session(|session| session.annotation(key, value));
+    }
+
+    /// Runs a function with the namespace unless the subsegment has ended.
+    pub fn with_namespace<R>(&self, f: impl FnOnce(&mut N) -> R) -> Option<R> {
+        self.with_session(|session| session.namespace_mut().map(f))
+            .flatten()
+    }
+
+    /// Returns whether the subsegment has ended.
+    pub fn is_ended(&self) -> bool {
+        self.lock().is_none()
+    }
+
+    /// Ends the subsegment and sends it.
+    ///
+    /// Does nothing if the subsegment has already ended. See
+    /// [`SubsegmentSession::end`].
+    pub fn end(&self) -> Result<()> {
+        self.end_with(&())
+    }
+
+    /// Ends the subsegment with the outcome of the operation it traces.
+    ///
+    /// Does nothing if the subsegment has already ended. See
+    /// [`SubsegmentSession::end_with`].
+    pub fn end_with<O>(&self, outcome: &O) -> Result<()>
+    where
+        O: Outcome + ?Sized,
+    {
+        // ends outside the lock
+        let session = self.lock().take();
+        session.map_or(Ok(()), |session| session.end_with(outcome))
+    }
+
+    fn with_session<R>(&self, f: impl FnOnce(&mut SubsegmentSession<C, N>) -> R) -> Option<R> {
+        self.lock().as_mut().map(f)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<SubsegmentSession<C, N>>> {
+        self.session.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<C, N> Clone for DetachedSession<C, N>
+where
+    C: Client,
+    N: Namespace + Send + Sync,
+{
+    fn clone(&self) -> Self {
+        Self {
+            session: self.session.clone(),
+        }
+    }
+}
+
+/// Outcome of an operation traced in a subsegment.
+///
+/// See [`SubsegmentSession::end_with`].
+pub trait Outcome {
+    /// Records the outcome in a subsegment.
+    fn record(&self, subsegment: &mut Subsegment);
+}
+
+impl Outcome for () {
+    fn record(&self, _subsegment: &mut Subsegment) {}
+}
+
+impl<T, E> Outcome for std::result::Result<T, E>
+where
+    E: Display,
+{
+    fn record(&self, subsegment: &mut Subsegment) {
+        if let Err(e) = self {
+            record_error(subsegment, e);
+        }
+    }
+}
+
+/// Outcome of an operation dropped before it completed.
+pub(crate) struct Cancelled;
+
+impl Outcome for Cancelled {
+    fn record(&self, subsegment: &mut Subsegment) {
+        subsegment
+            .annotations
+            .get_or_insert_with(Default::default)
+            .insert("cancelled".to_string(), Annotation::Bool(true));
+    }
+}
+
+// a cause referring to an exception by ID is replaced with a description of
+// the error, whose exception refers to the former as its cause.
+fn record_error<E>(subsegment: &mut Subsegment, error: &E)
+where
+    E: Display,
+{
+    subsegment.fault = true;
+    let mut exception = Exception {
+        id: SegmentId::new().to_string(),
+        message: Some(error.to_string()),
+        type_: Some(std::any::type_name::<E>().to_string()),
+        ..Exception::default()
+    };
+    match &mut subsegment.cause {
+        Some(Cause::Description { exceptions, .. }) => exceptions.push(exception),
+        cause => {
+            if let Some(Cause::Name(id)) = cause.take() {
+                exception.cause = Some(id);
             }
-            Self::Failed => (),
+            *cause = Some(Cause::Description {
+                working_directory: String::new(),
+                paths: Vec::new(),
+                exceptions: vec![exception],
+            })
+        }
+    }
+}
+
+/// Where a subsegment and its children are recorded.
+///
+/// Opaque; it can be neither inspected nor built outside this crate.
+#[derive(Debug)]
+pub struct SessionScope {
+    name_prefix: String,
+    // collector of the parent if the subsegment is embedded
+    parent: Option<Embedded>,
+    // collector of the children if they are embedded
+    children: Option<Embedded>,
+    in_progress: InProgress,
+    // in-progress document waiting for the duration of the policy
+    pending_in_progress: Option<Deferred>,
+    // whether the subsegment has ended; prevents sending it again on drop
+    ended: bool,
+    // number of ancestors entered in this process
+    depth: usize,
+    // precursor tracker of the subsegment and its siblings if enabled
+    siblings: Option<Precursors>,
+    // precursor tracker of the children if enabled
+    children_precursors: Option<Precursors>,
+}
+
+impl<C, N> Drop for SubsegmentSession<C, N>
+where
+    C: Client,
+    N: Namespace + Send + Sync,
+{
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            report_error("failed to end subsegment", &e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::*;
+    use crate::context::Context as _;
+    use crate::error::Result;
+    use crate::namespace::CustomNamespace;
+    use crate::testing::{context, FailingClient, RecordingClient};
+
+    #[test]
+    fn session_should_send_children_separately_by_default() {
+        let client = RecordingClient::default();
+        let session = context(&client).enter_subsegment(CustomNamespace::new("parent"));
+        let child = session
+            .child_context()
+            .unwrap()
+            .enter_subsegment(CustomNamespace::new("child"));
+        drop(child);
+        drop(session);
+        let documents = client.documents();
+        assert_eq!(documents.len(), 4);
+        assert_eq!(documents[2]["name"], "child");
+        assert_eq!(documents[2]["parent_id"], documents[0]["id"]);
+    }
+
+    #[test]
+    fn session_should_embed_children() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_embedded_subsegments(100);
+        let session = context.enter_subsegment(CustomNamespace::new("parent"));
+        let children = session.child_context().unwrap();
+        {
+            let child = children.enter_subsegment(CustomNamespace::new("child"));
+            let _grandchild = child
+                .child_context()
+                .unwrap()
+                .enter_subsegment(CustomNamespace::new("grandchild"));
         }
+        drop(children.enter_subsegment(CustomNamespace::new("sibling")));
+        drop(session);
+        let documents = client.documents();
+        // in-progress and completed parent only
+        assert_eq!(documents.len(), 2);
+        let parent = &documents[1];
+        assert_eq!(parent["name"], "parent");
+        assert_eq!(parent["type"], "subsegment");
+        let children = parent["subsegments"].as_array().unwrap();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0]["name"], "child");
+        assert!(children[0].get("trace_id").is_none());
+        assert!(children[0].get("parent_id").is_none());
+        assert!(children[0].get("type").is_none());
+        assert_eq!(children[0]["subsegments"][0]["name"], "grandchild");
+        assert_eq!(children[1]["name"], "sibling");
+    }
+
+    #[test]
+    fn session_should_stream_children_beyond_threshold() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_embedded_subsegments(2);
+        let session = context.enter_subsegment(CustomNamespace::new("parent"));
+        let children = session.child_context().unwrap();
+        for _ in 0..3 {
+            drop(children.enter_subsegment(CustomNamespace::new("child")));
+        }
+        drop(session);
+        let documents = client.documents();
+        assert_eq!(documents.len(), 3);
+        let streamed = &documents[1];
+        assert_eq!(streamed["name"], "child");
+        assert_eq!(streamed["type"], "subsegment");
+        assert_eq!(streamed["parent_id"], documents[0]["id"]);
+        assert_eq!(documents[2]["subsegments"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn session_should_send_children_ending_after_parent() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_embedded_subsegments(100);
+        let session = context.enter_subsegment(CustomNamespace::new("parent"));
+        let child = session
+            .child_context()
+            .unwrap()
+            .enter_subsegment(CustomNamespace::new("late"));
+        drop(session);
+        drop(child);
+        let documents = client.documents();
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents[2]["name"], "late");
+        assert_eq!(documents[2]["parent_id"], documents[0]["id"]);
+    }
+
+    #[test]
+    fn session_should_not_send_in_progress_with_never_policy() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_in_progress_policy(InProgressPolicy::Never);
+        let mut session = context.enter_subsegment(CustomNamespace::new("quiet"));
+        session.heartbeat();
+        drop(session);
+        let documents = client.documents();
+        assert_eq!(documents.len(), 1);
+        assert!(documents[0].get("in_progress").is_none());
+    }
+
+    #[test]
+    fn session_should_defer_in_progress_until_threshold() {
+        let client = RecordingClient::default();
+        let context = context(&client)
+            .with_in_progress_policy(InProgressPolicy::After(Duration::from_secs(60)));
+        let mut session = context.enter_subsegment(CustomNamespace::new("slow"));
+        assert_eq!(client.documents().len(), 0);
+        // stands in for the threshold elapsing
+        session.heartbeat();
+        assert_eq!(client.documents().len(), 1);
+        assert_eq!(client.documents()[0]["in_progress"], true);
+        drop(session);
+        let documents = client.documents();
+        assert_eq!(documents.len(), 2);
+        assert!(documents[1].get("in_progress").is_none());
+    }
+
+    #[test]
+    fn session_should_not_send_in_progress_once_ended() {
+        let client = RecordingClient::default();
+        let context = context(&client)
+            .with_in_progress_policy(InProgressPolicy::After(Duration::from_millis(20)));
+        drop(context.enter_subsegment(CustomNamespace::new("fast")));
+        std::thread::sleep(Duration::from_millis(200));
+        let documents = client.documents();
+        assert_eq!(documents.len(), 1);
+        assert!(documents[0].get("in_progress").is_none());
+    }
+
+    #[test]
+    fn heartbeat_should_send_pending_in_progress_now() {
+        let client = RecordingClient::default();
+        let context = context(&client)
+            .with_in_progress_policy(InProgressPolicy::After(Duration::from_secs(60)));
+        let mut session = context.enter_subsegment(CustomNamespace::new("slow"));
+        session.heartbeat();
+        session.heartbeat();
+        assert_eq!(client.documents().len(), 1);
+        assert_eq!(client.documents()[0]["in_progress"], true);
+    }
+
+    #[derive(Clone, Debug)]
+    struct InProgressFailingClient(RecordingClient);
+
+    impl Client for InProgressFailingClient {
+        fn send<S>(&self, data: &S) -> Result<()>
+        where
+            S: Serialize,
+        {
+            let value = serde_json::to_value(data)?;
+            if value.get("in_progress").is_some() {
+                return Err(std::io::Error::other("unreachable").into());
+            }
+            self.0.send(&value)
+        }
+    }
+
+    #[test]
+    fn session_should_send_completed_subsegment_if_entry_fails() {
+        let client = RecordingClient::default();
+        let context = SubsegmentContext::with_header(
+            InProgressFailingClient(client.clone()),
+            "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1"
+                .parse()
+                .unwrap(),
+        );
+        let session = context.enter_subsegment(CustomNamespace::new("flaky"));
+        assert!(session.x_amzn_trace_id().is_some());
+        drop(session);
+        let documents = client.documents();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0]["name"], "flaky");
+    }
+
+    #[test]
+    fn session_should_not_send_again_on_drop_after_end() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_in_progress_policy(InProgressPolicy::Never);
+        let session = context.enter_subsegment(CustomNamespace::new("explicit"));
+        session.end().unwrap();
+        let documents = client.documents();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0]["name"], "explicit");
+        assert!(documents[0].get("fault").is_none());
+    }
+
+    #[test]
+    fn session_should_record_error_of_result() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_in_progress_policy(InProgressPolicy::Never);
+        let session = context.enter_subsegment(CustomNamespace::new("parse"));
+        session.end_with(&"forty-two".parse::<u32>()).unwrap();
+        let documents = client.documents();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0]["fault"], true);
+        let exception = &documents[0]["cause"]["exceptions"][0];
+        assert_eq!(exception["message"], "invalid digit found in string");
+        assert!(exception["type"]
+            .as_str()
+            .unwrap()
+            .ends_with("ParseIntError"));
+        assert_eq!(exception["id"].as_str().unwrap().len(), 16);
+    }
+
+    #[test]
+    fn record_error_should_keep_cause_referring_to_exception() {
+        let mut subsegment = Subsegment::begin(
+            "1-65dfb5a1-0123456789abcdef01234567".parse().unwrap(),
+            None,
+            "parse",
+        );
+        subsegment.cause = Some(Cause::Name("0123456789abcdef".to_string()));
+        record_error(&mut subsegment, &"boom");
+        assert!(subsegment.fault);
+        match subsegment.cause {
+            Some(Cause::Description { exceptions, .. }) => {
+                assert_eq!(exceptions.len(), 1);
+                assert_eq!(exceptions[0].message.as_deref(), Some("boom"));
+                assert_eq!(exceptions[0].cause.as_deref(), Some("0123456789abcdef"));
+            }
+            cause => panic!("unexpected cause: {cause:?}"),
+        }
+    }
+
+    #[test]
+    fn session_should_not_record_ok_result() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_in_progress_policy(InProgressPolicy::Never);
+        let session = context.enter_subsegment(CustomNamespace::new("parse"));
+        session.end_with(&"42".parse::<u32>()).unwrap();
+        let documents = client.documents();
+        assert_eq!(documents.len(), 1);
+        assert!(documents[0].get("fault").is_none());
+        assert!(documents[0].get("cause").is_none());
+    }
+
+    #[test]
+    fn session_should_return_send_error_on_end() {
+        let context = context(&FailingClient).with_in_progress_policy(InProgressPolicy::Never);
+        let session = context.enter_subsegment(CustomNamespace::new("lost"));
+        assert!(session.end().is_err());
+    }
+
+    #[test]
+    fn detached_session_should_end_exactly_once() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_in_progress_policy(InProgressPolicy::Never);
+        let session = context
+            .enter_subsegment(CustomNamespace::new("shared"))
+            .detach();
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let session = session.clone();
+                std::thread::spawn(move || {
+                    session.annotation(format!("worker{i}"), true);
+                    session.end().unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(session.is_ended());
+        assert!(session.x_amzn_trace_id().is_none());
+        drop(session);
+        let documents = client.documents();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0]["name"], "shared");
+        // at least the first worker annotates before ending
+        assert!(!documents[0]["annotations"].as_object().unwrap().is_empty());
+    }
+
+    #[test]
+    fn detached_session_should_end_when_last_handle_is_dropped() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_in_progress_policy(InProgressPolicy::Never);
+        let session = context
+            .enter_subsegment(CustomNamespace::new("dropped"))
+            .detach();
+        let handle = session.clone();
+        drop(session);
+        assert!(client.documents().is_empty());
+        handle.annotation("late", 1);
+        drop(handle);
+        let documents = client.documents();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0]["annotations"]["late"], 1);
+    }
+
+    #[test]
+    fn session_should_chain_sequential_precursors() {
+        let client = RecordingClient::default();
+        let context = context(&client)
+            .with_in_progress_policy(InProgressPolicy::Never)
+            .with_precursors();
+        let first = context.enter_subsegment(CustomNamespace::new("first"));
+        let children = first.child_context().unwrap();
+        drop(children.enter_subsegment(CustomNamespace::new("child")));
+        drop(first);
+        drop(context.enter_subsegment(CustomNamespace::new("second")));
+        let documents = client.documents();
+        assert_eq!(documents.len(), 3);
+        // children are tracked separately from their parent
+        assert!(documents[0].get("precursor_ids").is_none());
+        assert!(documents[1].get("precursor_ids").is_none());
+        assert_eq!(documents[2]["precursor_ids"][0], documents[1]["id"]);
+    }
+
+    #[test]
+    fn session_should_follow_parallel_precursors() {
+        let client = RecordingClient::default();
+        let context = context(&client)
+            .with_in_progress_policy(InProgressPolicy::Never)
+            .with_precursors();
+        let left = context.enter_subsegment(CustomNamespace::new("left"));
+        let right = context.enter_subsegment(CustomNamespace::new("right"));
+        drop(right);
+        drop(left);
+        drop(context.enter_subsegment(CustomNamespace::new("join")));
+        drop(context.enter_subsegment(CustomNamespace::new("next")));
+        let documents = client.documents();
+        assert_eq!(documents.len(), 4);
+        assert!(documents[0].get("precursor_ids").is_none());
+        assert!(documents[1].get("precursor_ids").is_none());
+        assert_eq!(
+            documents[2]["precursor_ids"],
+            serde_json::json!([documents[0]["id"], documents[1]["id"]]),
+        );
+        assert_eq!(
+            documents[3]["precursor_ids"],
+            serde_json::json!([documents[2]["id"]]),
+        );
+    }
+
+    #[test]
+    fn session_should_record_explicit_precursors() {
+        let client = RecordingClient::default();
+        let context = context(&client).with_in_progress_policy(InProgressPolicy::Never);
+        let first = context.enter_subsegment(CustomNamespace::new("first"));
+        let first_id = first.id().unwrap();
+        drop(first);
+        let mut second = context.enter_subsegment(CustomNamespace::new("second"));
+        second.precursor_id(first_id.clone());
+        second.precursor_id(first_id.clone());
+        drop(second);
+        let documents = client.documents();
+        assert_eq!(
+            documents[1]["precursor_ids"],
+            serde_json::json!([first_id.to_string()]),
+        );
     }
 }
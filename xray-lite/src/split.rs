@@ -0,0 +1,201 @@
+//! Splitting of oversized segment documents.
+//!
+//! The X-Ray daemon drops UDP packets that exceed its buffer. A document that
+//! does not fit in a packet is split as follows:
+//!
+//! 1. Embedded subsegments, largest first, are streamed as separate documents
+//!    until the parent fits. A streamed subsegment is given `trace_id`,
+//!    `parent_id` and `type: subsegment`, and is split recursively.
+//! 2. If the document still does not fit, its metadata is replaced with
+//!    `{"xray_lite": {"truncated": true}}`.
+
+use serde_json::{json, Map, Value};
+
+use crate::error::{Error, Result};
+
+/// Key of the metadata that replaces truncated metadata.
+pub(crate) const TRUNCATED_METADATA: &str = "xray_lite";
+
+/// Splits a document into serialized documents of at most `max_size` bytes.
+///
+/// The parent comes before its streamed subsegments in the returned list.
+/// Fails with [`Error::DocumentTooLarge`] if a document cannot be made to fit.
+pub(crate) fn split_document(document: Value, max_size: usize) -> Result<Vec<Vec<u8>>> {
+    let mut documents = Vec::new();
+    split_into(document, max_size, &mut documents)?;
+    Ok(documents)
+}
+
+fn split_into(document: Value, max_size: usize, documents: &mut Vec<Vec<u8>>) -> Result<()> {
+    let bytes = serde_json::to_vec(&document)?;
+    if bytes.len() <= max_size {
+        documents.push(bytes);
+        return Ok(());
+    }
+    let Value::Object(mut fields) = document else {
+        return Err(Error::DocumentTooLarge {
+            size: bytes.len(),
+            max_size,
+        });
+    };
+    let streamed = stream_subsegments(&mut fields, max_size)?;
+    let mut bytes = serde_json::to_vec(&fields)?;
+    if bytes.len() > max_size && fields.contains_key("metadata") {
+        fields.insert(
+            "metadata".into(),
+            json!({ TRUNCATED_METADATA: { "truncated": true } }),
+        );
+        bytes = serde_json::to_vec(&fields)?;
+    }
+    if bytes.len() > max_size {
+        return Err(Error::DocumentTooLarge {
+            size: bytes.len(),
+            max_size,
+        });
+    }
+    documents.push(bytes);
+    for subsegment in streamed {
+        split_into(subsegment, max_size, documents)?;
+    }
+    Ok(())
+}
+
+// detaches embedded subsegments, largest first, until `fields` fits.
+fn stream_subsegments(fields: &mut Map<String, Value>, max_size: usize) -> Result<Vec<Value>> {
+    let subsegments = match fields.remove("subsegments") {
+        Some(Value::Array(subsegments)) if !subsegments.is_empty() => subsegments,
+        Some(other) => {
+            fields.insert("subsegments".into(), other);
+            return Ok(Vec::new());
+        }
+        None => return Ok(Vec::new()),
+    };
+    let (Some(trace_id), Some(id)) = (fields.get("trace_id"), fields.get("id")) else {
+        // cannot be streamed without knowing where they belong
+        fields.insert("subsegments".into(), Value::Array(subsegments));
+        return Ok(Vec::new());
+    };
+    let (trace_id, id) = (trace_id.clone(), id.clone());
+    let mut sized = subsegments
+        .into_iter()
+        .map(|subsegment| Ok((serde_json::to_vec(&subsegment)?.len(), subsegment)))
+        .collect::<Result<Vec<_>>>()?;
+    sized.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+    // size of the parent with all of its subsegments still embedded
+    let mut size = serde_json::to_vec(fields)?.len()
+        + ",\"subsegments\":[]".len()
+        + sized.iter().map(|(size, _)| size + 1).sum::<usize>()
+        - 1;
+    let mut streamed = Vec::new();
+    let mut embedded = Vec::new();
+    for (subsegment_size, mut subsegment) in sized {
+        if size > max_size {
+            size -= subsegment_size + 1;
+            if let Value::Object(subsegment) = &mut subsegment {
+                subsegment.insert("trace_id".into(), trace_id.clone());
+                subsegment.insert("parent_id".into(), id.clone());
+                subsegment.insert("type".into(), "subsegment".into());
+            }
+            streamed.push(subsegment);
+        } else {
+            embedded.push(subsegment);
+        }
+    }
+    if !embedded.is_empty() {
+        fields.insert("subsegments".into(), Value::Array(embedded));
+    }
+    Ok(streamed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(documents: &[Vec<u8>]) -> Vec<Value> {
+        documents
+            .iter()
+            .map(|bytes| serde_json::from_slice(bytes).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn split_document_should_keep_small_documents() {
+        let document =
+            json!({"trace_id": "1-5759e988-bd862e3fe1be46a994272793", "id": "53995c3f42cd8ad8"});
+        let documents = split_document(document.clone(), 1024).unwrap();
+        assert_eq!(parse(&documents), vec![document]);
+    }
+
+    #[test]
+    fn split_document_should_stream_largest_subsegments() {
+        let document = json!({
+            "trace_id": "1-5759e988-bd862e3fe1be46a994272793",
+            "id": "53995c3f42cd8ad8",
+            "name": "parent",
+            "subsegments": [
+                {"id": "0000000000000001", "name": "small"},
+                {"id": "0000000000000002", "name": "large", "metadata": {"blob": "x".repeat(200)}},
+            ],
+        });
+        let documents = parse(&split_document(document, 200).unwrap());
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0]["subsegments"].as_array().unwrap().len(), 1);
+        assert_eq!(documents[0]["subsegments"][0]["name"], "small");
+        assert_eq!(documents[1]["name"], "large");
+        assert_eq!(
+            documents[1]["trace_id"],
+            "1-5759e988-bd862e3fe1be46a994272793"
+        );
+        assert_eq!(documents[1]["parent_id"], "53995c3f42cd8ad8");
+        assert_eq!(documents[1]["type"], "subsegment");
+        // the streamed subsegment still had to be truncated
+        assert_eq!(
+            documents[1]["metadata"],
+            json!({"xray_lite": {"truncated": true}})
+        );
+    }
+
+    #[test]
+    fn split_document_should_split_nested_subsegments() {
+        let document = json!({
+            "trace_id": "1-5759e988-bd862e3fe1be46a994272793",
+            "id": "53995c3f42cd8ad8",
+            "subsegments": [{
+                "id": "0000000000000001",
+                "subsegments": [{"id": "0000000000000002", "name": "x".repeat(180)}],
+            }],
+        });
+        let documents = parse(&split_document(document, 320).unwrap());
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents[2]["parent_id"], "0000000000000001");
+        assert_eq!(
+            documents[2]["trace_id"],
+            "1-5759e988-bd862e3fe1be46a994272793"
+        );
+    }
+
+    #[test]
+    fn split_document_should_truncate_metadata() {
+        let document = json!({
+            "id": "53995c3f42cd8ad8",
+            "metadata": {"default": {"blob": "x".repeat(1000)}},
+        });
+        let documents = parse(&split_document(document, 200).unwrap());
+        assert_eq!(
+            documents,
+            vec![json!({
+                "id": "53995c3f42cd8ad8",
+                "metadata": {"xray_lite": {"truncated": true}},
+            })]
+        );
+    }
+
+    #[test]
+    fn split_document_should_fail_if_document_cannot_fit() {
+        let document = json!({"id": "53995c3f42cd8ad8", "name": "x".repeat(1000)});
+        assert!(matches!(
+            split_document(document, 200),
+            Err(Error::DocumentTooLarge { .. })
+        ));
+    }
+}
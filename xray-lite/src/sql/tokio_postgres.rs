@@ -0,0 +1,125 @@
+//! Tracing for [`tokio_postgres`](https://docs.rs/tokio-postgres).
+
+use std::future::Future;
+
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, Error, Row};
+
+use crate::context::Context;
+use crate::namespace::SqlNamespace;
+use crate::sql::trace_query;
+
+/// Database type recorded in `sql.database_type`.
+const DATABASE_TYPE: &str = "PostgreSQL";
+
+/// Driver recorded in `sql.driver_version`.
+const DRIVER: &str = "tokio-postgres";
+
+/// Extension of [`tokio_postgres::Client`] that runs queries in SQL
+/// subsegments.
+///
+/// `name` should identify the database; e.g., `"orders@db.example.com"`.
+/// Use [`trace_query`] directly if you want to record more details like the
+/// connection URL.
+///
+/// ```no_run
+/// use xray_lite::{DaemonClient, SubsegmentContext};
+/// use xray_lite::sql::tokio_postgres::ClientExt as _;
+///
+/// async fn count_orders(
+///     context: &SubsegmentContext<DaemonClient>,
+///     client: &tokio_postgres::Client,
+/// ) -> Result<i64, tokio_postgres::Error> {
+///     let rows = client
+///         .query_traced(context, "orders@localhost", "SELECT count(*) FROM orders", &[])
+///         .await?;
+///     Ok(rows[0].get(0))
+/// }
+/// ```
+pub trait ClientExt {
+    /// Runs [`Client::query`] in a SQL subsegment.
+    fn query_traced<X>(
+        &self,
+        context: &X,
+        name: &str,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<Vec<Row>, Error>>
+    where
+        X: Context;
+
+    /// Runs [`Client::execute`] in a SQL subsegment.
+    fn execute_traced<X>(
+        &self,
+        context: &X,
+        name: &str,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<u64, Error>>
+    where
+        X: Context;
+}
+
+impl ClientExt for Client {
+    fn query_traced<X>(
+        &self,
+        context: &X,
+        name: &str,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<Vec<Row>, Error>>
+    where
+        X: Context,
+    {
+        trace_query(
+            context,
+            namespace(name, statement),
+            self.query(statement, params),
+        )
+    }
+
+    fn execute_traced<X>(
+        &self,
+        context: &X,
+        name: &str,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<u64, Error>>
+    where
+        X: Context,
+    {
+        trace_query(
+            context,
+            namespace(name, statement),
+            self.execute(statement, params),
+        )
+    }
+}
+
+fn namespace(name: &str, statement: &str) -> SqlNamespace {
+    SqlNamespace::new(name, statement)
+        .with_database_type(DATABASE_TYPE)
+        .with_driver_version(DRIVER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::namespace::Namespace as _;
+    use crate::segment::Subsegment;
+
+    #[test]
+    fn namespace_should_record_database_type_and_driver() {
+        let namespace = namespace("orders@localhost", "DELETE FROM orders WHERE id = 7");
+        assert_eq!(namespace.name(""), "orders@localhost");
+        let mut subsegment = Subsegment::default();
+        namespace.update_subsegment(&mut subsegment);
+        let sql = subsegment.sql.expect("sql");
+        assert_eq!(
+            sql.sanitized_query.unwrap(),
+            "DELETE FROM orders WHERE id = ?"
+        );
+        assert_eq!(sql.database_type.unwrap(), "PostgreSQL");
+        assert_eq!(sql.driver_version.unwrap(), "tokio-postgres");
+    }
+}
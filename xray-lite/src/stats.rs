@@ -0,0 +1,233 @@
+//! Statistics of clients.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::error::Error;
+
+/// Statistics of a client.
+///
+/// Clones share the same counters; clones of a client report to the same
+/// statistics. [`ClientStats::snapshot`] reads the counters, and
+/// [`ClientStats::emit_emf`] writes them as a CloudWatch Embedded Metric
+/// Format (EMF) log line.
+///
+/// The `record_*` methods are meant for implementations of
+/// [`Client`][crate::Client]. A document that cannot be serialized counts as a
+/// serialization failure, whereas a document too large to be split into
+/// packets ([`Error::DocumentTooLarge`]) or dropped from a queue counts as a
+/// queue drop.
+///
+/// ```
+/// use xray_lite::{Client as _, DaemonClient};
+///
+/// let client = DaemonClient::new(([127, 0, 0, 1], 2000).into()).unwrap();
+/// client.send(&serde_json::json!({"id": "53995c3f42cd8ad8"})).unwrap();
+/// let stats = client.stats().unwrap().snapshot();
+/// assert_eq!(stats.documents_sent, 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ClientStats {
+    inner: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    documents_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    serialization_failures: AtomicU64,
+    socket_errors: AtomicU64,
+    queue_drops: AtomicU64,
+    // last snapshot emitted in EMF
+    emitted: Mutex<StatsSnapshot>,
+}
+
+/// Snapshot of [`ClientStats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    /// Number of documents sent.
+    pub documents_sent: u64,
+    /// Number of bytes sent.
+    pub bytes_sent: u64,
+    /// Number of documents that could not be serialized.
+    pub serialization_failures: u64,
+    /// Number of failures of the underlying socket, writer, or API.
+    pub socket_errors: u64,
+    /// Number of documents dropped without being sent; e.g., from a full
+    /// queue, or too large to be split into packets.
+    pub queue_drops: u64,
+}
+
+impl ClientStats {
+    /// Creates statistics with zero counters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a document of `bytes` sent.
+    pub fn record_sent(&self, bytes: usize) {
+        self.inner.documents_sent.fetch_add(1, Ordering::Relaxed);
+        self.inner
+            .bytes_sent
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Records a document that could not be serialized.
+    pub fn record_serialization_failure(&self) {
+        self.inner
+            .serialization_failures
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a failure of the underlying socket, writer, or API.
+    pub fn record_socket_error(&self) {
+        self.inner.socket_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records `count` documents dropped from a queue.
+    pub fn record_queue_drops(&self, count: usize) {
+        self.inner
+            .queue_drops
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// Records a document that could not be made into packets.
+    pub(crate) fn record_packet_failure(&self, error: &Error) {
+        match error {
+            Error::DocumentTooLarge { .. } => self.record_queue_drops(1),
+            _ => self.record_serialization_failure(),
+        }
+    }
+
+    /// Returns the current values of the counters.
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            documents_sent: self.inner.documents_sent.load(Ordering::Relaxed),
+            bytes_sent: self.inner.bytes_sent.load(Ordering::Relaxed),
+            serialization_failures: self.inner.serialization_failures.load(Ordering::Relaxed),
+            socket_errors: self.inner.socket_errors.load(Ordering::Relaxed),
+            queue_drops: self.inner.queue_drops.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Writes the counters as a CloudWatch Embedded Metric Format log line.
+    ///
+    /// The metrics are the increases since the last call so that they can be
+    /// summed up in CloudWatch. Call this periodically or at the end of each
+    /// Lambda invocation with [`std::io::stdout`] as `out`.
+    pub fn emit_emf(&self, namespace: &str, out: &mut impl Write) -> io::Result<()> {
+        let snapshot = self.snapshot();
+        let delta = {
+            let mut emitted = self
+                .inner
+                .emitted
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let delta = snapshot.since(&emitted);
+            *emitted = snapshot;
+            delta
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        serde_json::to_writer(&mut *out, &delta.to_emf(namespace, timestamp))?;
+        out.write_all(b"\n")
+    }
+}
+
+impl StatsSnapshot {
+    /// Returns the increases since a given snapshot.
+    pub fn since(&self, earlier: &StatsSnapshot) -> StatsSnapshot {
+        StatsSnapshot {
+            documents_sent: self.documents_sent.saturating_sub(earlier.documents_sent),
+            bytes_sent: self.bytes_sent.saturating_sub(earlier.bytes_sent),
+            serialization_failures: self
+                .serialization_failures
+                .saturating_sub(earlier.serialization_failures),
+            socket_errors: self.socket_errors.saturating_sub(earlier.socket_errors),
+            queue_drops: self.queue_drops.saturating_sub(earlier.queue_drops),
+        }
+    }
+
+    /// Returns the snapshot as a CloudWatch Embedded Metric Format document.
+    ///
+    /// `timestamp` is in milliseconds since the Unix epoch.
+    pub fn to_emf(&self, namespace: &str, timestamp: u64) -> Value {
+        json!({
+            "_aws": {
+                "Timestamp": timestamp,
+                "CloudWatchMetrics": [{
+                    "Namespace": namespace,
+                    "Dimensions": [[]],
+                    "Metrics": [
+                        {"Name": "DocumentsSent", "Unit": "Count"},
+                        {"Name": "BytesSent", "Unit": "Bytes"},
+                        {"Name": "SerializationFailures", "Unit": "Count"},
+                        {"Name": "SocketErrors", "Unit": "Count"},
+                        {"Name": "QueueDrops", "Unit": "Count"},
+                    ],
+                }],
+            },
+            "DocumentsSent": self.documents_sent,
+            "BytesSent": self.bytes_sent,
+            "SerializationFailures": self.serialization_failures,
+            "SocketErrors": self.socket_errors,
+            "QueueDrops": self.queue_drops,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_stats_should_be_shared_by_clones() {
+        let stats = ClientStats::new();
+        stats.clone().record_sent(10);
+        stats.record_sent(5);
+        stats.record_serialization_failure();
+        stats.record_socket_error();
+        stats.record_queue_drops(3);
+        assert_eq!(
+            stats.snapshot(),
+            StatsSnapshot {
+                documents_sent: 2,
+                bytes_sent: 15,
+                serialization_failures: 1,
+                socket_errors: 1,
+                queue_drops: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn emit_emf_should_write_increases_since_last_emission() {
+        let stats = ClientStats::new();
+        stats.record_sent(100);
+        let mut out = Vec::new();
+        stats.emit_emf("xray-lite", &mut out).unwrap();
+        stats.record_sent(20);
+        stats.emit_emf("xray-lite", &mut out).unwrap();
+        let lines: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0]["_aws"]["CloudWatchMetrics"][0]["Namespace"],
+            "xray-lite"
+        );
+        assert_eq!(lines[0]["DocumentsSent"], 1);
+        assert_eq!(lines[0]["BytesSent"], 100);
+        assert_eq!(lines[1]["DocumentsSent"], 1);
+        assert_eq!(lines[1]["BytesSent"], 20);
+        assert_eq!(lines[1]["QueueDrops"], 0);
+    }
+}
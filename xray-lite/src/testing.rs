@@ -0,0 +1,68 @@
+//! Helpers for tests of this crate and the crates that extend it.
+//!
+//! Available with the `test-util` feature. Not covered by semver.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::client::Client;
+use crate::context::SubsegmentContext;
+use crate::error::{Error, Result};
+
+/// Trace header of the contexts made by [`context`].
+pub const TRACE_HEADER: &str =
+    "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1";
+
+/// Client that records documents in memory.
+///
+/// Clones share the recorded documents.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingClient(Arc<Mutex<Vec<Value>>>);
+
+impl RecordingClient {
+    /// Returns the documents sent so far.
+    pub fn documents(&self) -> Vec<Value> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl Client for RecordingClient {
+    fn send<S>(&self, data: &S) -> Result<()>
+    where
+        S: Serialize,
+    {
+        let document = serde_json::to_value(data)?;
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(document);
+        Ok(())
+    }
+}
+
+/// Client that fails to send any document.
+#[derive(Clone, Debug)]
+pub struct FailingClient;
+
+impl Client for FailingClient {
+    fn send<S>(&self, _data: &S) -> Result<()>
+    where
+        S: Serialize,
+    {
+        Err(Error::TransportClosed { source: None })
+    }
+}
+
+/// Creates a context of [`TRACE_HEADER`] that sends documents to a given
+/// client.
+pub fn context<C>(client: &C) -> SubsegmentContext<C>
+where
+    C: Client,
+{
+    SubsegmentContext::with_header(client.clone(), TRACE_HEADER.parse().unwrap())
+}
@@ -1,7 +1,11 @@
-use crate::{epoch::Seconds, hexbytes::Bytes};
+use crate::{
+    epoch::Seconds,
+    error::Error,
+    hexbytes::{is_hex, Bytes},
+};
 use rand::RngCore;
 use serde::{de, ser, Serializer};
-use std::fmt;
+use std::{fmt, str::FromStr};
 /// Coorelates a string of spans together
 ///
 /// Users need only refer to displability
@@ -25,6 +29,24 @@ impl TraceId {
     }
 }
 
+impl FromStr for TraceId {
+    type Err = Error;
+
+    /// Parses a trace ID in the form `1-{8 hex digits}-{24 hex digits}`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('-');
+        let valid = parts.next() == Some("1")
+            && parts.next().is_some_and(|time| is_hex(time, 8))
+            && parts.next().is_some_and(|id| is_hex(id, 24))
+            && parts.next().is_none();
+        if valid {
+            Ok(TraceId::Rendered(s.into()))
+        } else {
+            Err(Error::InvalidTraceId(s.into()))
+        }
+    }
+}
+
 impl Default for TraceId {
     fn default() -> Self {
         TraceId::new()
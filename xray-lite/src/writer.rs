@@ -0,0 +1,310 @@
+//! Client that writes documents to a file, stdout, or any writer.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Stdout, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::Serialize;
+
+use crate::client::{Client, DaemonClient};
+use crate::error::Result;
+use crate::stats::ClientStats;
+
+/// Client that writes documents as newline-delimited JSON.
+///
+/// Each document is written on its own line. With
+/// [`WriterClient::with_daemon_framing`], each document is preceded by the
+/// `{"format": "json", "version": 1}` header line as in packets for the X-Ray
+/// daemon.
+///
+/// This is useful to capture traces for local debugging and tests without
+/// running the X-Ray daemon.
+///
+/// ```
+/// use xray_lite::{Context as _, CustomNamespace, SubsegmentContext, WriterClient};
+///
+/// let client = WriterClient::new(Vec::new());
+/// let context = SubsegmentContext::with_header(
+///     client.clone(),
+///     "Root=1-65dfb5a1-0123456789abcdef01234567;Parent=0123456789abcdef;Sampled=1"
+///         .parse()
+///         .unwrap(),
+/// );
+/// drop(context.enter_subsegment(CustomNamespace::new("captured")));
+/// drop(context);
+/// let output = String::from_utf8(client.into_inner().unwrap()).unwrap();
+/// assert_eq!(output.lines().count(), 2);
+/// ```
+#[derive(Debug)]
+pub struct WriterClient<W> {
+    writer: Arc<Mutex<W>>,
+    daemon_framing: bool,
+    stats: ClientStats,
+}
+
+impl<W> Clone for WriterClient<W> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: self.writer.clone(),
+            daemon_framing: self.daemon_framing,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<W> WriterClient<W>
+where
+    W: Write,
+{
+    /// Creates a client that writes documents to a given writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+            daemon_framing: false,
+            stats: ClientStats::new(),
+        }
+    }
+
+    /// Updates the client to precede each document with the header of the
+    /// X-Ray daemon.
+    pub fn with_daemon_framing(self) -> Self {
+        Self {
+            daemon_framing: true,
+            ..self
+        }
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&self) -> Result<()> {
+        self.lock().flush()?;
+        Ok(())
+    }
+
+    /// Returns the underlying writer if this is the last clone of the client.
+    pub fn into_inner(self) -> Option<W> {
+        Arc::into_inner(self.writer)
+            .map(|writer| writer.into_inner().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, W> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl WriterClient<Stdout> {
+    /// Creates a client that writes documents to stdout.
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl WriterClient<File> {
+    /// Creates a client that appends documents to a file at `path`.
+    pub fn append(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+}
+
+impl WriterClient<RotatingFile> {
+    /// Creates a client that appends documents to a file at `path`, which is
+    /// rotated when it exceeds `max_bytes`.
+    ///
+    /// See [`RotatingFile`] for details.
+    pub fn rotating(path: impl Into<PathBuf>, max_bytes: u64) -> Result<Self> {
+        Ok(Self::new(RotatingFile::new(path, max_bytes)?))
+    }
+}
+
+impl<W> Client for WriterClient<W>
+where
+    W: Write + std::fmt::Debug + Send,
+{
+    fn send<S>(&self, data: &S) -> Result<()>
+    where
+        S: Serialize,
+    {
+        let mut line = Vec::new();
+        if self.daemon_framing {
+            line.extend_from_slice(DaemonClient::HEADER);
+            line.extend_from_slice(DaemonClient::DELIMITER);
+        }
+        serde_json::to_writer(&mut line, data).inspect_err(|_| {
+            self.stats.record_serialization_failure();
+        })?;
+        line.extend_from_slice(DaemonClient::DELIMITER);
+        // a single write so that a document is never split across files
+        self.lock().write_all(&line).inspect_err(|_| {
+            self.stats.record_socket_error();
+        })?;
+        self.stats.record_sent(line.len());
+        Ok(())
+    }
+
+    fn stats(&self) -> Option<&ClientStats> {
+        Some(&self.stats)
+    }
+}
+
+/// File rotated by size.
+///
+/// When a write would make the file exceed the maximum size, the file is
+/// renamed with the suffix `.1` and a new file is started. Older files are
+/// shifted to `.2`, `.3`, and so on, and files beyond the maximum number of
+/// rotated files are removed. A single write is never split across files.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Default maximum number of rotated files kept.
+    pub const DEFAULT_MAX_FILES: usize = 5;
+
+    /// Opens a file at `path` in append mode.
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files: Self::DEFAULT_MAX_FILES,
+            file,
+            size,
+        })
+    }
+
+    /// Updates the maximum number of rotated files kept.
+    pub fn with_max_files(self, max_files: usize) -> Self {
+        Self { max_files, ..self }
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("xray-lite-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("traces.ndjson")
+    }
+
+    #[test]
+    fn writer_client_should_write_ndjson() {
+        let client = WriterClient::new(Vec::new());
+        client.send(&json!({"id": 1})).unwrap();
+        client.send(&json!({"id": 2})).unwrap();
+        assert_eq!(
+            client.into_inner().unwrap(),
+            b"{\"id\":1}\n{\"id\":2}\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn writer_client_should_write_daemon_framing() {
+        let client = WriterClient::new(Vec::new()).with_daemon_framing();
+        client.send(&json!({"id": 1})).unwrap();
+        assert_eq!(
+            client.into_inner().unwrap(),
+            b"{\"format\": \"json\", \"version\": 1}\n{\"id\":1}\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn writer_client_should_keep_writer_while_cloned() {
+        let client = WriterClient::new(Vec::new());
+        let clone = client.clone();
+        assert!(client.into_inner().is_none());
+        assert!(clone.into_inner().is_some());
+    }
+
+    #[test]
+    fn rotating_file_should_rotate_by_size() {
+        let path = temp_path("rotate");
+        let client = WriterClient::new(RotatingFile::new(&path, 20).unwrap().with_max_files(2));
+        for i in 0..4 {
+            // 13 bytes per line; two do not fit in 20 bytes
+            client.send(&json!({"id": 10000 + i})).unwrap();
+        }
+        client.flush().unwrap();
+        let read = |index: Option<usize>| {
+            let mut path = path.clone().into_os_string();
+            if let Some(index) = index {
+                path.push(format!(".{index}"));
+            }
+            fs::read_to_string(path).unwrap()
+        };
+        assert_eq!(read(None), "{\"id\":10003}\n");
+        assert_eq!(read(Some(1)), "{\"id\":10002}\n");
+        assert_eq!(read(Some(2)), "{\"id\":10001}\n");
+        assert!(!Path::new(&format!("{}.3", path.display())).exists());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn rotating_file_should_append_to_existing_file() {
+        let path = temp_path("append");
+        fs::write(&path, "{\"id\":0}\n").unwrap();
+        let client = WriterClient::rotating(&path, 1024).unwrap();
+        client.send(&json!({"id": 1})).unwrap();
+        client.flush().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\"id\":0}\n{\"id\":1}\n"
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}